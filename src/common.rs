@@ -1,6 +1,9 @@
 //! Shared functions
 
-use crate::prelude::*;
+use crate::{
+    format_string::{Argument, FormatString},
+    prelude::*,
+};
 
 /// Get an [Expr] from [attributes][Attribute]
 pub fn attrs_get_value<'a>(
@@ -83,21 +86,849 @@ impl<'a> From<AttrsGetValueError<'a>> for AttrsGetLitStrError<'a> {
 }
 impl std::error::Error for AttrsGetLitStrError<'_> {}
 
-/// Converts `variable` to something that implements display with the `display` closure if it exists, or return `variable`.
+/// Converts a [syn::Error] so it can be reported with [proc_macro_error].
+pub fn syn_error_to_diagnostic(error: syn::Error) -> Diagnostic {
+    Diagnostic::spanned(error.span(), Level::Error, error.to_string())
+}
+
+/// How the `display` attribute converts a field.
+pub enum FieldDisplay {
+    /// A closure or function called with the field
+    Call(Box<Expr>),
+    /// A function called with a reference to the field, `with = ...`
+    With(Box<Expr>),
+    /// A format string written with the value of an [Option] field when it is [Some], `optional = "..."`
+    Optional(LitStr),
+    /// The items of a collection with `separator` between them, `join = "..."`
+    Join {
+        separator: LitStr,
+        /// A function called with a reference to every item, `with = ...`
+        with: Option<Box<Expr>>,
+        /// How many items are written before the rest is counted, `max = ...`
+        max: Option<LitInt>,
+    },
+}
+impl Parse for FieldDisplay {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !(input.peek(Ident) && input.peek2(Token![=]) && !input.peek2(Token![==])) {
+            return Ok(Self::Call(Box::new(input.parse()?)));
+        }
+        let mut optional = None;
+        let mut separator = None;
+        let mut with = None;
+        let mut max = None;
+
+        while !input.is_empty() {
+            let key = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+
+            match key.to_string().as_str() {
+                "join" => separator = Some(input.parse::<LitStr>()?),
+                "max" => max = Some(input.parse::<LitInt>()?),
+                "optional" => optional = Some(input.parse::<LitStr>()?),
+                "with" => with = Some(Box::new(input.parse::<Expr>()?)),
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        key,
+                        "unknown argument for attribute `display`, expected `join`, `max`, `optional` or `with`",
+                    ))
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        match (optional, separator, with, max) {
+            (Some(optional), None, None, None) => Ok(Self::Optional(optional)),
+            (Some(optional), ..) => Err(syn::Error::new(
+                optional.span(),
+                "`optional` cannot be combined with other arguments",
+            )),
+            (None, Some(separator), with, max) => Ok(Self::Join {
+                separator,
+                with,
+                max,
+            }),
+            (None, None, _, Some(max)) => Err(syn::Error::new(max.span(), "`max` requires `join`")),
+            (None, None, Some(with), None) => Ok(Self::With(with)),
+            (None, None, None, None) => Err(input.error("expected an argument")),
+        }
+    }
+}
+
+/// Get the conversion from a `display` attribute.
+///
+/// It can be written as `#[display(...)]` or inside a string literal as `#[display = "..."]`.
+pub fn attrs_get_display(attrs: &[Attribute]) -> Option<FieldDisplay> {
+    let attr = attrs.iter().find(|attr| attr.path().is_ident("display"))?;
+    let display = match &attr.meta {
+        Meta::List(list) => list.parse_args::<FieldDisplay>(),
+        Meta::NameValue(name_value) => match &name_value.value {
+            // the tokens get the span of the string literal
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(lit_str),
+                ..
+            }) => lit_str.parse::<FieldDisplay>(),
+            expr => Err(syn::Error::new_spanned(
+                expr,
+                "attribute `display` requires a string, or the closure or function in `#[display(...)]`",
+            )),
+        },
+        Meta::Path(path) => Err(syn::Error::new_spanned(
+            path,
+            "attribute `display` requires a closure or function",
+        )),
+    };
+
+    display
+        .map_err(|error| syn_error_to_diagnostic(error).emit())
+        .ok()
+}
+
+/// The options of the `error_defaults` attribute.
+#[derive(Default)]
+pub struct ErrorDefaults {
+    /// Create a message from the name of the item when `format` is missing
+    pub humanize: bool,
+}
+impl Parse for ErrorDefaults {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut defaults = Self::default();
+
+        for option in input.parse_terminated(Ident::parse, Token![,])? {
+            if option != "humanize" {
+                return Err(syn::Error::new_spanned(
+                    option,
+                    "unknown option for attribute `error_defaults`, expected `humanize`",
+                ));
+            }
+            defaults.humanize = true;
+        }
+
+        Ok(defaults)
+    }
+}
+
+/// Get the options from an `error_defaults` attribute, which is written as `#[error_defaults(...)]`.
+pub fn attrs_get_defaults(attrs: &[Attribute]) -> ErrorDefaults {
+    let Some(attr) = attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("error_defaults"))
+    else {
+        return ErrorDefaults::default();
+    };
+
+    attr.parse_args::<ErrorDefaults>()
+        .map_err(|error| syn_error_to_diagnostic(error).emit())
+        .unwrap_or_default()
+}
+
+/// Creates a message from an identifier and the fields, such as `file not found (path: "foo.txt")` for `FileNotFound { path }`.
+///
+/// Fields use [Debug] unless they have a `display`, sources are left out since they are reported by [std::error::Error::source].
+pub fn humanized_format(ident: &Ident, fields: &[ErrorField<'_>]) -> FormatString {
+    let mut message = String::new();
+    let ident = ident.to_string();
+    let chars = ident.chars().collect::<Vec<_>>();
+
+    for (i, c) in chars.iter().enumerate() {
+        // a word starts at an uppercase letter after a lowercase one, or at the last uppercase letter of an acronym
+        let starts_word = i > 0
+            && c.is_uppercase()
+            && (chars[i - 1].is_lowercase()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()));
+
+        if *c == '_' {
+            message.push(' ');
+            continue;
+        }
+        if starts_word && !message.ends_with(' ') {
+            message.push(' ');
+        }
+        message.extend(c.to_lowercase());
+    }
+    let placeholders = fields
+        .iter()
+        .filter(|field| !field.source && !field.backtrace)
+        .map(|field| {
+            let spec = if field.display.is_some() { "" } else { ":?" };
+
+            match &field.member {
+                Member::Named(ident) => format!("{0}: {{{0}{1}}}", ident, spec),
+                Member::Unnamed(index) => format!("{{{}{}}}", index.index, spec),
+            }
+        })
+        .collect::<Vec<_>>();
+    if !placeholders.is_empty() {
+        message.push_str(&format!(" ({})", placeholders.join(", ")));
+    }
+
+    FormatString::parse(&LitStr::new(message.trim(), Span::call_site())).unwrap()
+}
+
+/// Get the error code from a `code` attribute as a string.
+///
+/// It can be a string or integer literal, `#[code]` without a value uses `discriminant` if it is an integer literal.
+pub fn attrs_get_code(attrs: &[Attribute], discriminant: Option<&Expr>) -> Option<LitStr> {
+    let attr = attrs.iter().find(|attr| attr.path().is_ident("code"))?;
+    let lit = match &attr.meta {
+        Meta::NameValue(name_value) => match &name_value.value {
+            Expr::Lit(ExprLit { lit, .. }) => Some(lit),
+            _ => None,
+        },
+        Meta::Path(_) => match discriminant {
+            Some(Expr::Lit(ExprLit { lit, .. })) => Some(lit),
+            _ => {
+                Diagnostic::spanned(
+                    attr.span(),
+                    Level::Error,
+                    String::from("`#[code]` without a value requires an integer discriminant"),
+                )
+                .help(String::from("add `#[code = \"...\"]`"))
+                .emit();
+                return None;
+            }
+        },
+        Meta::List(_) => None,
+    };
+
+    match lit {
+        Some(Lit::Str(lit_str)) => Some(lit_str.clone()),
+        Some(Lit::Int(lit_int)) => Some(LitStr::new(lit_int.base10_digits(), lit_int.span())),
+        _ => {
+            Diagnostic::spanned(
+                attr.span(),
+                Level::Error,
+                String::from("attribute `code` only accepts string or integer literals"),
+            )
+            .help(String::from("use `#[code = \"...\"]`"))
+            .emit();
+
+            None
+        }
+    }
+}
+
+/// Converts `variable` to something that implements display with the `display` attribute if it exists, or return `variable`.
 pub fn display_field<T: ToTokens + ?Sized>(
-    display: &Option<&LitStr>,
+    display: &Option<FieldDisplay>,
     variable: &T,
 ) -> TokenStream2 {
     match display {
-        Some(display) => {
-            let conversion = display
-                .parse()
-                .unwrap_or_else(|err| err.into_compile_error());
+        Some(FieldDisplay::Call(display)) => quote! {
+            (#display)(#variable)
+        },
+        Some(FieldDisplay::With(with)) => quote! {
+            #with(&#variable)
+        },
+        Some(FieldDisplay::Optional(optional)) => {
+            let display_fn = display_fn_struct();
+            let value = Ident::new("value", Span::mixed_site());
 
             quote! {
-                (#conversion)(#variable)
+                {
+                    #display_fn
+
+                    DisplayFn(|f: &mut std::fmt::Formatter<'_>| match &#variable {
+                        Some(#value) => write!(f, #optional, #value),
+                        None => Ok(()),
+                    })
+                }
+            }
+        }
+        Some(FieldDisplay::Join {
+            separator,
+            with,
+            max,
+        }) => {
+            let display_fn = display_fn_struct();
+            // the locals cannot be seen by the expressions given in the attribute
+            let count = Ident::new("count", Span::mixed_site());
+            let item = Ident::new("item", Span::mixed_site());
+            let write = match with {
+                Some(with) => quote! { #with(#item) },
+                None => quote! { #item },
+            };
+            // every item is counted, but only `max` of them are written
+            let (write_item, write_rest) = match max {
+                // nothing is written before the rest, so it does not start with a separator
+                Some(max) if max.base10_parse::<usize>().is_ok_and(|max| max == 0) => (
+                    TokenStream2::new(),
+                    quote! {
+                        if #count > 0 {
+                            write!(f, "\u{2026} (+{} more)", #count)?;
+                        }
+                    },
+                ),
+                Some(max) => (
+                    quote! {
+                        if #count < #max {
+                            if #count > 0 {
+                                f.write_str(#separator)?;
+                            }
+                            write!(f, "{}", #write)?;
+                        }
+                    },
+                    quote! {
+                        if #count > #max {
+                            write!(f, "{}\u{2026} (+{} more)", #separator, #count - #max)?;
+                        }
+                    },
+                ),
+                None => (
+                    quote! {
+                        if #count > 0 {
+                            f.write_str(#separator)?;
+                        }
+                        write!(f, "{}", #write)?;
+                    },
+                    TokenStream2::new(),
+                ),
+            };
+
+            quote! {
+                {
+                    #display_fn
+
+                    DisplayFn(|f: &mut std::fmt::Formatter<'_>| {
+                        let mut #count = 0_usize;
+                        // method resolution also finds the items behind the reference of a binding
+                        for #item in (&#variable).into_iter() {
+                            #write_item
+                            #count += 1;
+                        }
+                        #write_rest
+
+                        Ok(())
+                    })
+                }
             }
         }
         None => variable.to_token_stream(),
     }
 }
+
+/// Check if a path only attribute such as `#[source]` is present
+pub fn attrs_contains(attrs: &[Attribute], search: &str) -> bool {
+    attrs.iter().any(|attr| attr.path().is_ident(search))
+}
+
+/// Every attribute of the derives, which are not reported as misspelled by another derive.
+const ALL_ATTRIBUTES: &[&str] = &[
+    "backtrace",
+    "code",
+    "display",
+    "error_defaults",
+    "format",
+    "format_alt",
+    "from",
+    "kind",
+    "no_from",
+    "no_source",
+    "source",
+    "transparent",
+];
+
+/// Reports the attributes of a derive in `owned` that are repeated or have no effect at `place`, and other attributes that look like a misspelled one.
+///
+/// Only the attributes in `allowed` have an effect at `place`, which is a description such as "this field".
+pub fn validate_attrs(attrs: &[Attribute], owned: &[&str], allowed: &[&str], place: &str) {
+    let mut seen = Vec::new();
+
+    for attr in attrs {
+        let Some(ident) = attr.path().get_ident() else {
+            continue;
+        };
+        let name = ident.to_string();
+
+        if !owned.contains(&name.as_str()) {
+            if ALL_ATTRIBUTES.contains(&name.as_str()) {
+                continue;
+            }
+            if let Some(similar) = similar(&name, owned) {
+                Diagnostic::spanned(
+                    attr.span(),
+                    Level::Error,
+                    format!("unknown attribute `{}`", name),
+                )
+                .help(format!("did you mean `{}`?", similar))
+                .emit();
+            }
+        } else if !allowed.contains(&name.as_str()) {
+            Diagnostic::spanned(
+                attr.span(),
+                Level::Error,
+                format!("attribute `{}` has no effect on {}", name, place),
+            )
+            .help(String::from("remove it"))
+            .emit();
+        } else if seen.contains(&name) {
+            Diagnostic::spanned(
+                attr.span(),
+                Level::Error,
+                format!("duplicate attribute `{}`", name),
+            )
+            .help(String::from(
+                "only the first one is used, remove the others",
+            ))
+            .emit();
+        } else {
+            seen.push(name);
+        }
+    }
+}
+
+/// A field of a struct or enum variant and the attributes on it.
+pub struct ErrorField<'a> {
+    /// Whether the field is a [std::backtrace::Backtrace], by its type or the `backtrace` attribute
+    pub backtrace: bool,
+    /// The `display` attribute
+    pub display: Option<FieldDisplay>,
+    /// Whether `From` is implemented for the type of the field
+    pub from: bool,
+    /// How to access the field, `self.#member`
+    pub member: Member,
+    /// Whether the field is returned by [std::error::Error::source]
+    pub source: bool,
+    pub ty: &'a Type,
+}
+impl<'a> ErrorField<'a> {
+    pub fn new(index: usize, field: &'a Field) -> Self {
+        let from = attrs_contains(&field.attrs, "from");
+
+        Self {
+            backtrace: attrs_contains(&field.attrs, "backtrace")
+                || matches!(&field.ty, Type::Path(path) if path.qself.is_none()
+                    && path.path.segments.last().is_some_and(|segment| segment.ident == "Backtrace")),
+            display: attrs_get_display(&field.attrs),
+            from,
+            member: match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(index)),
+            },
+            // the value converted from is the source unless opted out
+            source: attrs_contains(&field.attrs, "source")
+                || (from && !attrs_contains(&field.attrs, "no_source")),
+            ty: &field.ty,
+        }
+    }
+
+    /// Collect all [fields][Fields] of a struct or variant.
+    pub fn from_fields(fields: &'a Fields) -> Vec<Self> {
+        fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| Self::new(i, field))
+            .collect()
+    }
+
+    /// The variable the field is bound to when it is destructured.
+    ///
+    /// It is hygienic and prefixed, so a field cannot shadow the formatter or other generated locals.
+    pub fn binding(&self) -> Ident {
+        match &self.member {
+            Member::Named(ident) => {
+                format_ident!("field_{}", ident.unraw(), span = Span::mixed_site())
+            }
+            Member::Unnamed(index) => {
+                format_ident!("arg_{}", index.index, span = Span::mixed_site())
+            }
+        }
+    }
+
+    /// The name a placeholder uses for the field, such as `path` or `0`.
+    pub fn name(&self) -> String {
+        match &self.member {
+            Member::Named(ident) => ident.to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        }
+    }
+
+    /// Binds the field to [Self::binding] inside a braced pattern.
+    pub fn pattern(&self) -> TokenStream2 {
+        let member = &self.member;
+        let binding = self.binding();
+
+        quote! { #member: #binding }
+    }
+}
+
+/// Implements `From` for the type of the field marked as `from` by creating `constructor` with it, if there is one.
+///
+/// The other fields are filled with [Default], or a captured backtrace.
+pub fn from_impl(
+    onto: &Ident,
+    generics: &Generics,
+    constructor: &TokenStream2,
+    fields: &[ErrorField<'_>],
+) -> Option<TokenStream2> {
+    let from = fields.iter().find(|field| field.from)?;
+
+    Some(convert_impl(
+        onto,
+        generics,
+        constructor,
+        fields,
+        (from, &quote! { error }),
+        from.ty,
+    ))
+}
+
+/// Implements `From<ty>` by creating `constructor` with `into.1` as the value of the field `into.0`, where `error` is the value converted from.
+///
+/// The other fields are filled with [Default], or a captured backtrace.
+pub fn convert_impl(
+    onto: &Ident,
+    generics: &Generics,
+    constructor: &TokenStream2,
+    fields: &[ErrorField<'_>],
+    into: (&ErrorField<'_>, &TokenStream2),
+    ty: &Type,
+) -> TokenStream2 {
+    let (into, value) = into;
+    let values = fields.iter().map(|field| {
+        let member = &field.member;
+
+        if field.member == into.member {
+            quote! { #member: #value }
+        } else if field.backtrace {
+            quote! { #member: std::backtrace::Backtrace::capture() }
+        } else {
+            quote! { #member: std::default::Default::default() }
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics From<#ty> for #onto #ty_generics #where_clause {
+            fn from(error: #ty) -> Self {
+                #constructor { #(#values),* }
+            }
+        }
+    }
+}
+
+/// Converts `variable`, a reference to `field` which is marked as `source`, into the return type of [std::error::Error::source].
+///
+/// This needs the trait from [as_dyn_error_trait] in scope.
+/// It is spanned to the type of the field, so a type that is not an error is reported there.
+pub fn source_expr<T: ToTokens + ?Sized>(field: &ErrorField<'_>, variable: &T) -> TokenStream2 {
+    quote_spanned! {field.ty.span()=>
+        Some((#variable).as_dyn_error())
+    }
+}
+
+/// Declares `AsDynError`, which converts errors into `&dyn Error`.
+///
+/// Unlike a cast, it also works on boxed trait objects such as `Box<dyn Error + Send + Sync>`, which do not implement [std::error::Error] themselves.
+pub fn as_dyn_error_trait() -> TokenStream2 {
+    let object_impls = [
+        quote! { dyn std::error::Error },
+        quote! { dyn std::error::Error + Send },
+        quote! { dyn std::error::Error + Send + Sync },
+    ]
+    .into_iter()
+    .map(|object| {
+        quote! {
+            impl AsDynError for #object + 'static {
+                fn as_dyn_error(&self) -> &(dyn std::error::Error + 'static) {
+                    self
+                }
+            }
+        }
+    });
+
+    quote! {
+        trait AsDynError {
+            fn as_dyn_error(&self) -> &(dyn std::error::Error + 'static);
+        }
+        impl<T: std::error::Error + 'static> AsDynError for T {
+            fn as_dyn_error(&self) -> &(dyn std::error::Error + 'static) {
+                self
+            }
+        }
+        #(#object_impls)*
+    }
+}
+
+/// Declares `DisplayFn`, which implements [Display][std::fmt::Display] by calling the closure it holds.
+///
+/// This lets generated code pass formatting logic as a format argument without allocating a [String].
+/// A width or precision it is formatted with is applied to the whole text, like [pad_flags].
+pub fn display_fn_struct() -> TokenStream2 {
+    let pad = pad_flags();
+
+    quote! {
+        struct DisplayFn<F>(F);
+        impl<F> std::fmt::Display for DisplayFn<F>
+        where
+            F: Fn(&mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error>,
+        {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+                #pad
+                (self.0)(f)
+            }
+        }
+    }
+}
+
+/// Gives the output of a derive to the compiler.
+///
+/// When errors were reported, the output is still given so they do not cascade.
+pub fn derive_output(output: TokenStream2) -> TokenStream {
+    set_dummy(output.clone());
+    output.into()
+}
+
+/// Implements [Display][std::fmt::Display] with `body` as the body of [fmt][std::fmt::Display::fmt].
+pub fn display_impl(ident: &Ident, generics: &Generics, body: TokenStream2) -> TokenStream2 {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics std::fmt::Display for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+                #body
+            }
+        }
+    }
+}
+
+/// Implements the methods and constants in `items` on the type, or nothing if there are none.
+pub fn inherent_impl(ident: &Ident, generics: &Generics, items: TokenStream2) -> TokenStream2 {
+    if items.is_empty() {
+        return TokenStream2::new();
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics #ident #ty_generics #where_clause {
+            #items
+        }
+    }
+}
+
+/// Creates `code` and `CODES`, where `code` is the body of the method and `codes` are every code it can return.
+pub fn code_items(code: TokenStream2, codes: &[&LitStr]) -> TokenStream2 {
+    quote! {
+        /// The error code
+        pub fn code(&self) -> &'static str {
+            #code
+        }
+
+        /// Every error code of the type
+        pub const CODES: &'static [&'static str] = &[#(#codes),*];
+    }
+}
+
+/// Creates `backtrace`, where `backtrace` is the body of the method.
+pub fn backtrace_items(backtrace: TokenStream2) -> TokenStream2 {
+    quote! {
+        /// The backtrace captured with the error
+        pub fn backtrace(&self) -> Option<&std::backtrace::Backtrace> {
+            #backtrace
+        }
+    }
+}
+
+/// Reports fields after the first that are a backtrace, which are not returned by `backtrace`.
+pub fn check_backtrace_fields(fields: &[ErrorField<'_>]) {
+    for field in fields.iter().filter(|field| field.backtrace).skip(1) {
+        Diagnostic::spanned(
+            field.ty.span(),
+            Level::Error,
+            String::from("there can only be one backtrace field"),
+        )
+        .help(String::from("remove the other backtrace fields"))
+        .emit();
+    }
+}
+
+/// Reports fields after the first that are a source, which are not returned by [std::error::Error::source].
+pub fn check_source_fields(fields: &[ErrorField<'_>]) {
+    for field in fields.iter().filter(|field| field.source).skip(1) {
+        Diagnostic::spanned(
+            field.ty.span(),
+            Level::Error,
+            String::from("there can only be one source field"),
+        )
+        .help(String::from(
+            "remove the other `#[source]` attributes, or add `#[no_source]` to the `from` field",
+        ))
+        .emit();
+    }
+}
+
+/// Applies the width and precision of the formatter to the whole message, the same way [str] does.
+///
+/// The message is only rendered into a [String] when one of them is set.
+pub fn pad_flags() -> TokenStream2 {
+    quote! {
+        if f.width().is_some() || f.precision().is_some() {
+            return f.pad(&if f.alternate() {
+                format!("{:#}", self)
+            } else {
+                format!("{}", self)
+            });
+        }
+    }
+}
+
+/// A function that writes the message, `fn = path` in `#[format(...)]`.
+struct FormatFn(Path);
+impl Parse for FormatFn {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Token![fn]>()?;
+        input.parse::<Token![=]>()?;
+
+        Ok(Self(input.parse()?))
+    }
+}
+
+/// Get the function from `#[format(fn = path)]`, which is called with the formatter instead of using a format string.
+pub fn attrs_get_format_fn(attrs: &[Attribute]) -> Option<Path> {
+    let attr = attrs.iter().find(|attr| attr.path().is_ident("format"))?;
+    let Meta::List(list) = &attr.meta else {
+        return None;
+    };
+
+    list.parse_args::<FormatFn>()
+        .map(|format_fn| format_fn.0)
+        .map_err(|error| {
+            syn_error_to_diagnostic(error)
+                .help(String::from(
+                    "use `#[format(fn = path)]` or `#[format = \"...\"]`",
+                ))
+                .emit()
+        })
+        .ok()
+}
+
+/// Get a [FormatString] from an attribute.
+///
+/// An attribute that is not a valid format string is reported and replaced with its text, so a missing `format` is not reported as well.
+/// `#[format(...)]` is left to [attrs_get_format_fn].
+pub fn attrs_get_format(attrs: &[Attribute], search: &str) -> Option<FormatString> {
+    let lit_str = match attrs_get_lit_str(attrs, search) {
+        Ok(lit_str) => lit_str,
+        Err(AttrsGetLitStrError::GetError(AttrsGetValueError::NotFound(_))) => return None,
+        Err(error) => {
+            let attr = attrs.iter().find(|attr| attr.path().is_ident(search))?;
+            if let (Meta::List(list), "format") = (&attr.meta, search) {
+                // the list form is reported by `attrs_get_format_fn`
+                return list
+                    .parse_args::<FormatFn>()
+                    .is_err()
+                    .then(|| FormatString::literal("", attr.span()));
+            }
+
+            Diagnostic::spanned(attr.span(), Level::Error, error.to_string())
+                .help(format!("use `#[{} = \"...\"]`", search))
+                .emit();
+            return Some(FormatString::literal("", attr.span()));
+        }
+    };
+
+    Some(FormatString::parse(lit_str).unwrap_or_else(|error| {
+        syn_error_to_diagnostic(error).emit();
+
+        FormatString::literal(&lit_str.value(), lit_str.span())
+    }))
+}
+
+/// Resolves a placeholder to one of `fields`, where `access` gives the value of a field.
+///
+/// The `display` of the field is only applied when there is no member access.
+pub fn resolve_field<'a, 'b>(
+    fields: &'b [ErrorField<'a>],
+    argument: &Argument,
+    access: impl FnOnce(&'b ErrorField<'a>) -> TokenStream2,
+) -> Option<TokenStream2> {
+    let field = fields
+        .iter()
+        .find(|field| argument.root.matches(&field.member))?;
+    let value = access(field);
+
+    Some(match &argument.members {
+        Some(members) => quote! { #value #members },
+        None => display_field(&field.display, &value),
+    })
+}
+
+/// The generated flag of whether the formatter is alternate, which is hygienic so fields cannot shadow it.
+pub fn alternate_ident() -> Ident {
+    Ident::new("alternate", Span::mixed_site())
+}
+
+/// Writes into the formatter with `format`, or with `format_alt` if the flag from [alternate_ident] is set.
+///
+/// `resolve` gives the values of the placeholders and `known` are the names it accepts, used for suggestions.
+pub fn write_format(
+    format: &FormatString,
+    format_alt: Option<&FormatString>,
+    known: &[String],
+    mut resolve: impl FnMut(&Argument) -> Option<TokenStream2>,
+) -> TokenStream2 {
+    let args = format.to_args(known, &mut resolve);
+
+    match format_alt {
+        Some(format_alt) => {
+            let alt_args = format_alt.to_args(known, &mut resolve);
+            let alternate = alternate_ident();
+
+            quote! {
+                if #alternate {
+                    write!(f, #alt_args)
+                } else {
+                    write!(f, #args)
+                }
+            }
+        }
+        None => quote! {
+            write!(f, #args)
+        },
+    }
+}
+
+/// Find the name in `known` closest to `name`, if any is close enough to be a typo.
+pub fn similar<'a, T: AsRef<str>>(name: &str, known: &'a [T]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|candidate| candidate.as_ref())
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.chars().count().max(3) / 3)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The edit distance between two strings, counting a swap of neighbouring characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let mut distance = (distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
@@ -1,276 +1,1000 @@
 use crate::{
-    common::{attrs_get_lit_str, display_field},
+    common::{
+        alternate_ident, attrs_contains, attrs_get_code, attrs_get_defaults, attrs_get_display,
+        attrs_get_format, attrs_get_format_fn, backtrace_items, check_backtrace_fields, code_items,
+        convert_impl, display_field, display_fn_struct, display_impl, from_impl, humanized_format,
+        inherent_impl, pad_flags, resolve_field, source_expr, syn_error_to_diagnostic,
+        validate_attrs, write_format, ErrorField, FieldDisplay,
+    },
+    format_string::{ArgumentRoot, FormatString},
     prelude::*,
 };
 
-/// Gets the `format` attribute but panics if not found.
-fn get_required_format<'a>(attrs: &'a [Attribute], ident: &Ident) -> &'a LitStr {
-    attrs_get_lit_str(attrs, "format").unwrap_or_else(|err| {
-        Diagnostic::new(
+/// Unwraps the `format` attribute of a variant that requires it, reporting it if not found.
+///
+/// A missing format is replaced with the name of the variant.
+fn get_required_format(format: Option<&FormatString>, ident: &Ident) -> FormatString {
+    format.cloned().unwrap_or_else(|| {
+        Diagnostic::spanned(
+            ident.span(),
             Level::Error,
             format!(
-                "failed to get required attribute `format` for variant `{}`: {}",
-                ident, err
+                "failed to get required attribute `format` for variant `{}`",
+                ident
             ),
         )
-        .abort()
+        .help(String::from("add `#[format = \"...\"]`"))
+        .emit();
+
+        FormatString::literal(&ident.to_string(), ident.span())
     })
 }
 
+/// The attributes [EnumError] reads
+const ATTRIBUTES: &[&str] = &[
+    "backtrace",
+    "code",
+    "display",
+    "error_defaults",
+    "format",
+    "format_alt",
+    "from",
+    "kind",
+    "no_from",
+    "transparent",
+];
+
+/// Types a variant is converted from through its field, `#[from(Type, ..., via = Intermediate)]` on a variant.
+pub struct FromVia {
+    types: Vec<Type>,
+    /// The type the values are converted into first, otherwise they are converted into the field directly
+    via: Option<Type>,
+}
+impl Parse for FromVia {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut types = Vec::new();
+        let mut via = None;
+
+        while !input.is_empty() {
+            if input.peek(Ident) && input.peek2(Token![=]) {
+                let key = input.parse::<Ident>()?;
+                input.parse::<Token![=]>()?;
+
+                if key != "via" {
+                    return Err(syn::Error::new_spanned(
+                        key,
+                        "unknown argument for attribute `from`, expected `via`",
+                    ));
+                }
+                if via.is_some() {
+                    return Err(syn::Error::new_spanned(key, "duplicate argument `via`"));
+                }
+                via = Some(input.parse::<Type>()?);
+            } else {
+                types.push(input.parse::<Type>()?);
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        if types.is_empty() {
+            return Err(input.error("expected the types to convert from"));
+        }
+
+        Ok(Self { types, via })
+    }
+}
+
+/// Get the types from `#[from(...)]` on a variant, `#[from]` alone is read by [ErrorField] and [EnumVariant].
+fn attrs_get_from_via(attrs: &[Attribute]) -> Option<FromVia> {
+    let attr = attrs.iter().find(|attr| attr.path().is_ident("from"))?;
+    let Meta::List(list) = &attr.meta else {
+        return None;
+    };
+
+    list.parse_args::<FromVia>()
+        .map_err(|error| {
+            syn_error_to_diagnostic(error)
+                .help(String::from(
+                    "use `#[from]` or `#[from(Type, via = Intermediate)]`",
+                ))
+                .emit()
+        })
+        .ok()
+}
+
 /// The possible enum variants.
 pub enum EnumVariant<'a> {
     AnonymousStruct {
         ident: &'a Ident,
-        fields: Vec<(Option<&'a LitStr>, &'a Ident)>,
-        format: &'a LitStr,
+        fields: Vec<ErrorField<'a>>,
+        format: Option<FormatString>,
+        format_alt: Option<FormatString>,
     },
     Discriminant {
         discriminant: &'a Expr,
-        format: Option<&'a LitStr>,
-        display: Option<&'a LitStr>,
+        format: Option<FormatString>,
+        format_alt: Option<FormatString>,
+        display: Option<FieldDisplay>,
         ident: &'a Ident,
     },
     SingleType {
         ident: &'a Ident,
-        display: Option<&'a LitStr>,
-        format: Option<&'a LitStr>,
-        ty: &'a Type,
+        /// The wrapped value, which also holds the `display` of the variant
+        field: ErrorField<'a>,
+        format: Option<FormatString>,
+        format_alt: Option<FormatString>,
+        /// Forward everything to the wrapped value
+        transparent: bool,
     },
     Tuple {
         ident: &'a Ident,
-        format: &'a LitStr,
-        displays: Vec<Option<&'a LitStr>>,
+        format: Option<FormatString>,
+        format_alt: Option<FormatString>,
+        fields: Vec<ErrorField<'a>>,
     },
     Unit {
         ident: &'a Ident,
-        format: &'a LitStr,
+        format: Option<FormatString>,
+        format_alt: Option<FormatString>,
     },
 }
 impl EnumVariant<'_> {
-    pub fn to_display_match_arm(&self) -> TokenStream2 {
+    pub fn ident(&self) -> &Ident {
+        match self {
+            Self::AnonymousStruct { ident, .. }
+            | Self::Discriminant { ident, .. }
+            | Self::SingleType { ident, .. }
+            | Self::Tuple { ident, .. }
+            | Self::Unit { ident, .. } => ident,
+        }
+    }
+    pub fn format(&self) -> Option<&FormatString> {
+        match self {
+            Self::AnonymousStruct { format, .. }
+            | Self::Discriminant { format, .. }
+            | Self::SingleType { format, .. }
+            | Self::Tuple { format, .. }
+            | Self::Unit { format, .. } => format.as_ref(),
+        }
+    }
+    pub fn format_alt(&self) -> Option<&FormatString> {
+        match self {
+            Self::AnonymousStruct { format_alt, .. }
+            | Self::Discriminant { format_alt, .. }
+            | Self::SingleType { format_alt, .. }
+            | Self::Tuple { format_alt, .. }
+            | Self::Unit { format_alt, .. } => format_alt.as_ref(),
+        }
+    }
+    /// The fields that can be accessed in `format`
+    pub fn fields(&self) -> &[ErrorField<'_>] {
+        match self {
+            Self::AnonymousStruct { fields, .. } | Self::Tuple { fields, .. } => fields,
+            Self::SingleType { field, .. } => std::slice::from_ref(field),
+            Self::Discriminant { .. } | Self::Unit { .. } => &[],
+        }
+    }
+    /// Creates a `format` from the name and fields of variants that require one but do not have it
+    pub fn humanize_missing_format(&mut self) {
         match self {
             Self::AnonymousStruct {
                 ident,
                 fields,
-                format,
-            } => {
-                let field_displays = fields
-                    .iter()
-                    .filter(|(display, _)| display.is_some())
-                    .map(|(display, ident)| {
-                        let display = display_field(display, ident);
-                        quote! {
-                            let #ident = #display;
-                        }
-                    })
-                    .collect::<TokenStream2>();
-                let fields = fields.iter().map(|(_, ident)| ident).collect::<Vec<_>>();
+                format: format @ None,
+                ..
+            }
+            | Self::Tuple {
+                ident,
+                fields,
+                format: format @ None,
+                ..
+            } => *format = Some(humanized_format(ident, fields)),
+            Self::Unit {
+                ident,
+                format: format @ None,
+                ..
+            } => *format = Some(humanized_format(ident, &[])),
+            _ => {}
+        }
+    }
+    pub fn is_transparent(&self) -> bool {
+        matches!(
+            self,
+            Self::SingleType {
+                transparent: true,
+                ..
+            }
+        )
+    }
+    /// Check if [Self::to_source_match_arm] can return something
+    pub fn has_source(&self) -> bool {
+        match self {
+            Self::AnonymousStruct { fields, .. } | Self::Tuple { fields, .. } => {
+                fields.iter().any(|field| field.source)
+            }
+            Self::SingleType {
+                field, transparent, ..
+            } => field.source || *transparent,
+            Self::Discriminant { .. } | Self::Unit { .. } => false,
+        }
+    }
+    /// Creates a match arm giving the backtrace field of the variant, if any
+    pub fn to_backtrace_match_arm(&self) -> TokenStream2 {
+        let ident = self.ident();
 
-                quote! {
-                    Self::#ident { #(#fields,)* } => {
-                        #field_displays
+        match self.fields().iter().find(|field| field.backtrace) {
+            Some(field) => {
+                let member = &field.member;
 
-                        format!(#format)
-                    },
+                quote! {
+                    Self::#ident { #member: backtrace, .. } => Some(backtrace),
                 }
             }
-            Self::Discriminant {
-                discriminant,
-                display,
-                ident,
-                format,
-            } => {
-                let display = display_field(display, discriminant);
+            None => quote! {
+                Self::#ident { .. } => None,
+            },
+        }
+    }
+    pub fn to_source_match_arm(&self) -> TokenStream2 {
+        let ident = self.ident();
 
-                match format {
-                    Some(format) => quote! {
-                        Self::#ident => format!(#format, #display),
-                    },
+        match self {
+            Self::AnonymousStruct { fields, .. } | Self::Tuple { fields, .. } => {
+                match fields.iter().find(|field| field.source) {
+                    Some(field) => {
+                        let member = &field.member;
+                        let source = source_expr(field, &quote! { source });
+
+                        quote! {
+                            Self::#ident { #member: source, .. } => #source,
+                        }
+                    }
                     None => quote! {
-                        Self::#ident => format!("{}", #display),
+                        Self::#ident { .. } => None,
                     },
                 }
             }
             Self::SingleType {
+                transparent: true, ..
+            } => quote! {
+                Self::#ident(source) => std::error::Error::source(source),
+            },
+            Self::SingleType { field, .. } if field.source => {
+                let source = source_expr(field, &quote! { source });
+
+                quote! {
+                    Self::#ident(source) => #source,
+                }
+            }
+            _ => quote! {
+                Self::#ident { .. } => None,
+            },
+        }
+    }
+
+    pub fn to_display_match_arm(&self) -> TokenStream2 {
+        let ident = self.ident();
+
+        match self {
+            Self::Discriminant {
+                discriminant,
                 display,
-                ident,
                 format,
+                format_alt,
                 ..
             } => {
-                let error_var = quote! { error };
-                let display = display_field(display, &error_var);
-
-                match format {
-                    Some(format) => {
-                        quote! {
-                            Self::#ident(#error_var) => format!(#format, #display),
+                let default_format = FormatString::positional();
+                let write = write_format(
+                    format.as_ref().unwrap_or(&default_format),
+                    format_alt.as_ref(),
+                    &[String::from("0")],
+                    |argument| match (&argument.root, &argument.members) {
+                        (ArgumentRoot::Index(0), Some(members)) => {
+                            Some(quote! { (#discriminant) #members })
                         }
-                    }
-                    None => {
-                        quote! {
-                            Self::#ident(#error_var) => format!("{}", #display),
+                        (ArgumentRoot::Index(0), None) => {
+                            Some(display_field(display, discriminant))
                         }
-                    }
+                        _ => None,
+                    },
+                );
+
+                quote! {
+                    Self::#ident => #write,
                 }
             }
-            Self::Tuple {
-                displays,
-                ident,
-                format,
-            } => {
-                let args = (0..displays.len())
-                    .map(|i| {
-                        format!("arg_{}", i)
-                            .parse()
-                            .unwrap_or_else(|error| syn::Error::from(error).into_compile_error())
-                    })
-                    .collect::<Vec<TokenStream2>>();
-                let arg_displays = args
-                    .iter()
-                    .zip(displays)
-                    .flat_map(|(arg, display)| {
-                        display.map(|display| {
-                            quote! {
-                                let #arg = #display;
-                            }
-                        })
+            Self::SingleType {
+                transparent: true, ..
+            } => quote! {
+                Self::#ident(error) => std::fmt::Display::fmt(error, f),
+            },
+            _ => {
+                let format = match self {
+                    Self::SingleType { format, .. } => {
+                        format.clone().unwrap_or_else(FormatString::positional)
+                    }
+                    _ => get_required_format(self.format(), ident),
+                };
+                let fields = self.fields();
+                // only the fields used by the format are bound
+                let mut used = Vec::<&ErrorField>::new();
+                let known = fields.iter().map(ErrorField::name).collect::<Vec<_>>();
+                let write = write_format(&format, self.format_alt(), &known, |argument| {
+                    resolve_field(fields, argument, |field| {
+                        if !used.iter().any(|used| used.member == field.member) {
+                            used.push(field);
+                        }
+
+                        field.binding().into_token_stream()
                     })
-                    .collect::<TokenStream2>();
+                });
+                let patterns = used.iter().map(|field| field.pattern());
 
                 quote! {
-                    Self::#ident(#(#args),*) => {
-                        #arg_displays
-                        format!(#format)
-                    },
+                    Self::#ident { #(#patterns,)* .. } => #write,
                 }
             }
-            Self::Unit { ident, format } => quote! {
-                Self::#ident => format!(#format),
-            },
         }
     }
-    pub fn to_from_impl(&self, onto: &Ident, generics: &Generics) -> Option<TokenStream2> {
-        let Self::SingleType {
-            ident, ty, format, ..
-        } = self
-        else {
-            return None;
+    /// Creates a match arm that calls `format_fn` with every field and the formatter
+    ///
+    /// When the message is `wrapped` in the format of the enum, the formatter it gets is not alternate,
+    /// so the function is given an alternate formatter through `DisplayFn` when `alternate` is set.
+    pub fn to_format_fn_match_arm(&self, format_fn: &Path, wrapped: bool) -> TokenStream2 {
+        let ident = self.ident();
+        let patterns = self.fields().iter().map(ErrorField::pattern);
+        let bindings = self
+            .fields()
+            .iter()
+            .map(ErrorField::binding)
+            .collect::<Vec<_>>();
+        let call = quote! { #format_fn(#(#bindings,)* f) };
+        let call = if wrapped {
+            let alternate = alternate_ident();
+
+            quote! {
+                if #alternate {
+                    write!(f, "{:#}", DisplayFn(|f: &mut std::fmt::Formatter<'_>| #call))
+                } else {
+                    #call
+                }
+            }
+        } else {
+            call
         };
-        if format.is_some() {
-            return None;
+
+        quote! {
+            Self::#ident { #(#patterns,)* .. } => #call,
         }
+    }
+    /// Implements `From` for the type of the field marked as `from`
+    pub fn to_from_impl(&self, onto: &Ident, generics: &Generics) -> Option<TokenStream2> {
+        let ident = self.ident();
 
-        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        from_impl(onto, generics, &quote! { Self::#ident }, self.fields())
+    }
+    /// The field `#[from(...)]` converts into, which is the field marked as `from` or the only field
+    fn conversion_field(&self) -> Option<&ErrorField<'_>> {
+        match self.fields() {
+            [field] => Some(field),
+            fields => fields.iter().find(|field| field.from),
+        }
+    }
+    /// Implements `From` for every type of `#[from(...)]`, converting them into the field
+    pub fn to_from_via_impls(
+        &self,
+        onto: &Ident,
+        generics: &Generics,
+        from_via: &FromVia,
+    ) -> TokenStream2 {
+        let ident = self.ident();
+        let Some(field) = self.conversion_field() else {
+            return TokenStream2::new();
+        };
+        let field_ty = field.ty;
 
-        Some(quote! {
-            #[automatically_derived]
-            impl #impl_generics From<#ty> for #onto #ty_generics #where_clause{
-                fn from(error: #ty) -> Self {
-                    Self::#ident(error)
-                }
-            }
-        })
+        from_via
+            .types
+            .iter()
+            .map(|ty| {
+                let value = match &from_via.via {
+                    Some(via) => quote! {
+                        <#field_ty as From<#via>>::from(<#via as From<#ty>>::from(error))
+                    },
+                    None => quote! { <#field_ty as From<#ty>>::from(error) },
+                };
+
+                convert_impl(
+                    onto,
+                    generics,
+                    &quote! { Self::#ident },
+                    self.fields(),
+                    (field, &value),
+                    ty,
+                )
+            })
+            .collect()
+    }
+}
+impl<'a> EnumVariant<'a> {
+    fn fields_mut(&mut self) -> &mut [ErrorField<'a>] {
+        match self {
+            Self::AnonymousStruct { fields, .. } | Self::Tuple { fields, .. } => fields,
+            Self::SingleType { field, .. } => std::slice::from_mut(field),
+            Self::Discriminant { .. } | Self::Unit { .. } => &mut [],
+        }
     }
 }
 impl<'a> From<&'a Variant> for EnumVariant<'a> {
     fn from(variant: &'a Variant) -> Self {
+        if attrs_contains(&variant.attrs, "transparent")
+            && !matches!(&variant.fields, Fields::Unnamed(fields) if fields.unnamed.len() == 1)
+        {
+            Diagnostic::spanned(
+                variant.ident.span(),
+                Level::Error,
+                format!(
+                    "`transparent` variant `{}` must have a single unnamed field",
+                    variant.ident
+                ),
+            )
+            .help(String::from("remove `#[transparent]`"))
+            .emit()
+        }
         if let Some(discriminant) = &variant.discriminant {
             if variant.fields == Fields::Unit {
                 return Self::Discriminant {
                     discriminant: &discriminant.1,
-                    display: attrs_get_lit_str(&variant.attrs, "display").ok(),
-                    format: attrs_get_lit_str(&variant.attrs, "format").ok(),
+                    display: attrs_get_display(&variant.attrs),
+                    format: attrs_get_format(&variant.attrs, "format"),
+                    format_alt: attrs_get_format(&variant.attrs, "format_alt"),
                     ident: &variant.ident,
                 };
             }
         }
 
         match &variant.fields {
-            Fields::Named(fields) => Self::AnonymousStruct {
+            Fields::Named(_) => Self::AnonymousStruct {
                 ident: &variant.ident,
-                fields: fields
-                    .named
-                    .iter()
-                    .map(|field| {
-                        (
-                            attrs_get_lit_str(&field.attrs, "display").ok(),
-                            field.ident.as_ref().unwrap(),
-                        )
-                    })
-                    .collect(),
-                format: get_required_format(&variant.attrs, &variant.ident),
+                fields: ErrorField::from_fields(&variant.fields),
+                format: attrs_get_format(&variant.attrs, "format"),
+                format_alt: attrs_get_format(&variant.attrs, "format_alt"),
             },
             Fields::Unnamed(fields) => {
                 if fields.unnamed.len() == 1 {
+                    let mut field = ErrorField::from_fields(&variant.fields).remove(0);
+                    let field_attrs = &fields.unnamed[0].attrs;
+                    let format = attrs_get_format(&variant.attrs, "format");
+                    // `#[format(fn = ...)]` is not read here but is still a format
+                    let wraps = !attrs_contains(&variant.attrs, "format");
+                    let has_attr = |search| {
+                        attrs_contains(&variant.attrs, search)
+                            || attrs_contains(field_attrs, search)
+                    };
+                    // `#[from(...)]` on the variant converts other types instead
+                    let from = attrs_contains(field_attrs, "from")
+                        || variant.attrs.iter().any(
+                            |attr| matches!(&attr.meta, Meta::Path(path) if path.is_ident("from")),
+                        );
+                    // variants that wrap another error are converted from it unless opted out
+                    field.from = !has_attr("no_from") && (wraps || from);
+                    // `Error` cannot tell if a wrapped value is an error, so only `#[from]` makes it a source
+                    field.source = has_attr("source") || (from && !has_attr("no_source"));
+                    field.display = attrs_get_display(&variant.attrs).or(field.display);
+                    let format_alt = attrs_get_format(&variant.attrs, "format_alt");
+                    let transparent = attrs_contains(&variant.attrs, "transparent");
+
+                    if transparent && (!wraps || format_alt.is_some() || field.display.is_some()) {
+                        Diagnostic::spanned(
+                            variant.ident.span(),
+                            Level::Error,
+                            format!(
+                                "`transparent` variant `{}` cannot have a `format` or `display`",
+                                variant.ident
+                            ),
+                        )
+                        .help(String::from(
+                            "remove either `#[transparent]` or the formatting",
+                        ))
+                        .emit()
+                    }
+
                     Self::SingleType {
-                        display: attrs_get_lit_str(&variant.attrs, "display").ok(),
                         ident: &variant.ident,
-                        format: attrs_get_lit_str(&variant.attrs, "format").ok(),
-                        ty: fields.unnamed.iter().map(|field| &field.ty).next().unwrap(),
+                        field,
+                        format,
+                        format_alt,
+                        transparent,
                     }
                 } else {
                     Self::Tuple {
-                        displays: fields
-                            .unnamed
-                            .iter()
-                            .map(|field| attrs_get_lit_str(&field.attrs, "display").ok())
-                            .collect(),
+                        fields: ErrorField::from_fields(&variant.fields),
                         ident: &variant.ident,
-                        format: get_required_format(&variant.attrs, &variant.ident),
+                        format: attrs_get_format(&variant.attrs, "format"),
+                        format_alt: attrs_get_format(&variant.attrs, "format_alt"),
                     }
                 }
             }
             Fields::Unit => Self::Unit {
                 ident: &variant.ident,
-                format: get_required_format(&variant.attrs, &variant.ident),
+                format: attrs_get_format(&variant.attrs, "format"),
+                format_alt: attrs_get_format(&variant.attrs, "format_alt"),
             },
         }
     }
 }
 
+/// Reports variants converted from more than one field and types that more than one variant is converted from.
+///
+/// Only the first conversion is kept, so the implementations do not conflict.
+/// The types that are converted from are returned with their variant.
+fn check_from_fields(variants: &mut [EnumVariant<'_>]) -> Vec<(String, Ident)> {
+    let mut types = Vec::<(String, Ident)>::new();
+
+    for variant in variants {
+        let ident = variant.ident().clone();
+        let mut found = false;
+
+        for field in variant.fields_mut().iter_mut().filter(|field| field.from) {
+            if found {
+                Diagnostic::spanned(
+                    field.ty.span(),
+                    Level::Error,
+                    format!("variant `{}` can only be converted from one field", ident),
+                )
+                .help(String::from("remove the other `#[from]` attributes"))
+                .emit();
+                field.from = false;
+                continue;
+            }
+            found = true;
+            let ty = field.ty.to_token_stream().to_string();
+
+            match types.iter().find(|(other, _)| *other == ty) {
+                Some((_, other)) => {
+                    Diagnostic::spanned(
+                        field.ty.span(),
+                        Level::Error,
+                        format!(
+                            "`From` for this type is already implemented by variant `{}`",
+                            other
+                        ),
+                    )
+                    .help(String::from("add `#[no_from]` to one of the variants"))
+                    .emit();
+                    field.from = false;
+                }
+                None => types.push((ty, ident.clone())),
+            }
+        }
+    }
+
+    types
+}
+
+/// Reports `#[from(...)]` on variants without a field to convert into and types that are already converted from.
+///
+/// Like [check_from_fields], only the first conversion of a type is kept.
+fn check_from_via(
+    variants: &[EnumVariant<'_>],
+    from_vias: &mut [Option<FromVia>],
+    mut types: Vec<(String, Ident)>,
+) {
+    for (variant, from_via) in variants.iter().zip(from_vias) {
+        let ident = variant.ident();
+        let Some(some) = from_via else {
+            continue;
+        };
+
+        if variant.conversion_field().is_none() {
+            Diagnostic::spanned(
+                ident.span(),
+                Level::Error,
+                format!("variant `{}` has no field to convert into", ident),
+            )
+            .help(String::from(
+                "add `#[from]` to the field the types are converted into",
+            ))
+            .emit();
+            *from_via = None;
+            continue;
+        }
+        some.types.retain(|ty| {
+            let name = ty.to_token_stream().to_string();
+
+            match types.iter().find(|(other, _)| *other == name) {
+                Some((_, other)) => {
+                    Diagnostic::spanned(
+                        ty.span(),
+                        Level::Error,
+                        format!(
+                            "`From` for this type is already implemented by variant `{}`",
+                            other
+                        ),
+                    )
+                    .help(String::from("remove the type from one of the variants"))
+                    .emit();
+
+                    false
+                }
+                None => {
+                    types.push((name, ident.clone()));
+
+                    true
+                }
+            }
+        });
+    }
+}
+
+/// Creates the body of `backtrace` if any variant has a backtrace field.
+pub fn backtrace_body(variants: &[EnumVariant<'_>]) -> Option<TokenStream2> {
+    if !variants
+        .iter()
+        .any(|variant| variant.fields().iter().any(|field| field.backtrace))
+    {
+        return None;
+    }
+    let match_arms = variants
+        .iter()
+        .map(EnumVariant::to_backtrace_match_arm)
+        .collect::<TokenStream2>();
+
+    Some(quote! {
+        match self {
+            #match_arms
+        }
+    })
+}
+
+/// Get the code of every variant, or none if no variant has a code.
+///
+/// Missing and duplicate codes are reported, a missing code is replaced with the name of the variant.
+fn get_codes(variants: &Punctuated<Variant, Token![,]>) -> Vec<LitStr> {
+    let codes = variants
+        .iter()
+        .map(|variant| {
+            attrs_get_code(
+                &variant.attrs,
+                variant
+                    .discriminant
+                    .as_ref()
+                    .map(|(_, discriminant)| discriminant),
+            )
+        })
+        .collect::<Vec<_>>();
+    if codes.iter().all(Option::is_none) {
+        return Vec::new();
+    }
+    let mut unique = Vec::<LitStr>::new();
+
+    for (variant, code) in variants.iter().zip(codes) {
+        let code = code.unwrap_or_else(|| {
+            // invalid codes are already reported
+            if attrs_contains(&variant.attrs, "code") {
+                return LitStr::new(&variant.ident.to_string(), variant.ident.span());
+            }
+            Diagnostic::spanned(
+                variant.ident.span(),
+                Level::Error,
+                format!("variant `{}` has no `code`", variant.ident),
+            )
+            .help(String::from(
+                "add `#[code = \"...\"]`, every variant needs one when any variant has one",
+            ))
+            .emit();
+
+            LitStr::new(&variant.ident.to_string(), variant.ident.span())
+        });
+
+        if let Some(first) = unique.iter().find(|first| first.value() == code.value()) {
+            Diagnostic::spanned(
+                code.span(),
+                Level::Error,
+                format!("duplicate code `{}`", code.value()),
+            )
+            .span_note(first.span(), String::from("first used here"))
+            .emit();
+        }
+        unique.push(code);
+    }
+
+    unique
+}
+
 pub struct EnumError<'a> {
+    /// The code of every variant in order, if any
+    codes: Vec<LitStr>,
     ident: &'a Ident,
-    format: Option<&'a LitStr>,
+    format: Option<FormatString>,
+    format_alt: Option<FormatString>,
+    /// The `#[format(fn = ...)]` of every variant in order
+    format_fns: Vec<Option<Path>>,
+    /// The `#[from(...)]` of every variant in order
+    from_vias: Vec<Option<FromVia>>,
     generics: &'a Generics,
+    /// Generate a fieldless enum of the variants with the `kind` attribute
+    kind: bool,
     variants: Vec<EnumVariant<'a>>,
+    vis: &'a Visibility,
 }
 impl EnumError<'_> {
     fn to_display_impl(&self) -> TokenStream2 {
-        let ident = &self.ident;
-        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let wrapped = self.format.is_some() || self.format_alt.is_some();
         let match_arms = self
             .variants
             .iter()
-            .map(|variant| variant.to_display_match_arm())
+            .zip(&self.format_fns)
+            .map(|(variant, format_fn)| match format_fn {
+                Some(format_fn) => variant.to_format_fn_match_arm(format_fn, wrapped),
+                None => variant.to_display_match_arm(),
+            })
             .collect::<TokenStream2>();
+        let pad = pad_flags();
+        // the flag is read once since the variant message may be written into another formatter
+        let alternate = (self.format_alt.is_some()
+            || self
+                .variants
+                .iter()
+                .any(|variant| variant.format_alt().is_some())
+            || (wrapped && self.format_fns.iter().any(Option::is_some)))
+        .then(|| {
+            let alternate = alternate_ident();
 
-        match self.format {
-            Some(format) => quote! {
-                #[automatically_derived]
-                impl #impl_generics std::fmt::Display for #ident #ty_generics #where_clause {
-                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                        write!(f, #format, match self {
-                            #match_arms
-                        })
-                    }
+            quote! {
+                let #alternate = f.alternate();
+            }
+        });
+
+        let body = match (&self.format, &self.format_alt) {
+            (None, None) => quote! {
+                #pad
+                #alternate
+
+                match self {
+                    #match_arms
                 }
             },
-            None => quote! {
-                #[automatically_derived]
-                impl #impl_generics std::fmt::Display for #ident #ty_generics #where_clause {
-                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                        write!(f, "{}", match self {
-                            #match_arms
-                        })
+            // the variant message is written through a closure so it can be an argument of `format`
+            (format, format_alt) => {
+                let default_format = FormatString::positional();
+                let display_fn = display_fn_struct();
+                let message = quote! {
+                    DisplayFn(|f: &mut std::fmt::Formatter<'_>| match self {
+                        #match_arms
+                    })
+                };
+                let mut known = ["0", "message", "variant"].map(String::from).to_vec();
+                if !self.codes.is_empty() {
+                    known.push(String::from("code"));
+                }
+                let write = write_format(
+                    format.as_ref().unwrap_or(&default_format),
+                    format_alt.as_ref(),
+                    &known,
+                    |argument| match (&argument.root, &argument.members) {
+                        (ArgumentRoot::Index(0), None) => Some(message.clone()),
+                        (ArgumentRoot::Name(name), None) if name == "message" => {
+                            Some(message.clone())
+                        }
+                        (ArgumentRoot::Name(name), members) if name == "variant" => {
+                            Some(quote! { self.variant_name() #members })
+                        }
+                        (ArgumentRoot::Name(name), members)
+                            if name == "code" && !self.codes.is_empty() =>
+                        {
+                            Some(quote! { self.code() #members })
+                        }
+                        _ => None,
+                    },
+                );
+
+                quote! {
+                    #pad
+                    #alternate
+                    #display_fn
+
+                    #write
+                }
+            }
+        };
+        // transparent variants get the formatter before any flags are applied
+        let transparent_arms = self
+            .variants
+            .iter()
+            .filter(|variant| variant.is_transparent())
+            .map(|variant| variant.to_display_match_arm())
+            .collect::<TokenStream2>();
+        let body = if transparent_arms.is_empty() {
+            body
+        } else {
+            quote! {
+                #[allow(unreachable_patterns)]
+                match self {
+                    #transparent_arms
+                    _ => {
+                        #body
                     }
                 }
+            }
+        };
+
+        display_impl(self.ident, self.generics, body)
+    }
+    /// Creates `code` and `CODES` if the variants have codes
+    fn to_code_items(&self) -> Option<TokenStream2> {
+        if self.codes.is_empty() {
+            return None;
+        }
+        let arms = self
+            .variants
+            .iter()
+            .zip(&self.codes)
+            .map(|(variant, code)| {
+                let ident = variant.ident();
+
+                quote! {
+                    Self::#ident { .. } => #code,
+                }
+            });
+
+        Some(code_items(
+            quote! {
+                match self {
+                    #(#arms)*
+                }
             },
+            &self.codes.iter().collect::<Vec<_>>(),
+        ))
+    }
+    /// The identifier of the enum created by [Self::to_kind_enum]
+    fn kind_ident(&self) -> Ident {
+        format_ident!("{}Kind", self.ident)
+    }
+    /// Creates `kind` if the enum has the `kind` attribute
+    fn to_kind_items(&self) -> Option<TokenStream2> {
+        if !self.kind {
+            return None;
+        }
+        let kind_ident = self.kind_ident();
+        let arms = self.variants.iter().map(|variant| {
+            let ident = variant.ident();
+
+            quote! {
+                Self::#ident { .. } => #kind_ident::#ident,
+            }
+        });
+
+        Some(quote! {
+            /// The variant without its fields
+            pub fn kind(&self) -> #kind_ident {
+                match self {
+                    #(#arms)*
+                }
+            }
+        })
+    }
+    /// Creates a fieldless enum with the same variants if the enum has the `kind` attribute
+    fn to_kind_enum(&self) -> TokenStream2 {
+        if !self.kind {
+            return TokenStream2::new();
+        }
+        let vis = self.vis;
+        let kind_ident = self.kind_ident();
+        let doc = format!("The variants of [`{}`] without their fields", self.ident);
+        let variants = self.variants.iter().map(EnumVariant::ident);
+        let arms = self.variants.iter().map(|variant| {
+            let ident = variant.ident();
+            let name = ident.to_string();
+
+            quote! {
+                Self::#ident => #name,
+            }
+        });
+
+        quote! {
+            #[doc = #doc]
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+            #vis enum #kind_ident {
+                #(#variants,)*
+            }
+            #[automatically_derived]
+            impl std::fmt::Display for #kind_ident {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+                    f.pad(match self {
+                        #(#arms)*
+                    })
+                }
+            }
+        }
+    }
+    /// Creates `variant_name`, `VARIANT_NAMES` and `format_template`
+    fn to_introspection_items(&self) -> TokenStream2 {
+        let names = self
+            .variants
+            .iter()
+            .map(|variant| variant.ident().to_string())
+            .collect::<Vec<_>>();
+        let name_arms = self.variants.iter().zip(&names).map(|(variant, name)| {
+            let ident = variant.ident();
+
+            quote! {
+                Self::#ident { .. } => #name,
+            }
+        });
+        let template_arms =
+            self.variants
+                .iter()
+                .zip(&self.format_fns)
+                .map(|(variant, format_fn)| {
+                    let ident = variant.ident();
+                    let template = match (variant.format(), format_fn) {
+                        // these do not write a format string
+                        (_, Some(_)) => quote! { None },
+                        (None, None) if variant.is_transparent() => quote! { None },
+                        (Some(format), None) => {
+                            let format = format.value();
+
+                            quote! { Some(#format) }
+                        }
+                        // variants without a format write their value
+                        (None, None) => quote! { Some("{}") },
+                    };
+
+                    quote! {
+                        Self::#ident { .. } => #template,
+                    }
+                });
+
+        quote! {
+            /// The name of the variant
+            pub fn variant_name(&self) -> &'static str {
+                match self {
+                    #(#name_arms)*
+                }
+            }
+
+            /// The name of every variant
+            pub const VARIANT_NAMES: &'static [&'static str] = &[#(#names),*];
+
+            /// The `format` string of the variant, or `None` if it is written by a function or transparent
+            pub fn format_template(&self) -> Option<&'static str> {
+                match self {
+                    #(#template_arms)*
+                }
+            }
         }
     }
+    fn to_inherent_impl(&self) -> TokenStream2 {
+        inherent_impl(
+            self.ident,
+            self.generics,
+            [
+                self.to_code_items(),
+                self.to_kind_items(),
+                backtrace_body(&self.variants).map(backtrace_items),
+                Some(self.to_introspection_items()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        )
+    }
     fn to_from_impls(&self) -> TokenStream2 {
         self.variants
             .iter()
-            .flat_map(|variant| variant.to_from_impl(self.ident, self.generics))
+            .zip(&self.from_vias)
+            .flat_map(|(variant, from_via)| {
+                let from_via = from_via
+                    .as_ref()
+                    .map(|from_via| variant.to_from_via_impls(self.ident, self.generics, from_via));
+
+                variant
+                    .to_from_impl(self.ident, self.generics)
+                    .into_iter()
+                    .chain(from_via)
+            })
             .collect()
     }
 }
@@ -281,23 +1005,145 @@ impl<'a> From<&'a DeriveInput> for EnumError<'a> {
                 .help(String::from("remove"))
                 .abort()
         };
-        let variants = data.variants.iter().map(EnumVariant::from).collect();
+        validate_attrs(
+            &input.attrs,
+            ATTRIBUTES,
+            &["error_defaults", "format", "format_alt", "kind"],
+            "this enum",
+        );
+        // also reports an invalid `#[format(...)]`, which `attrs_get_format` leaves out
+        if let Some(format_fn) = attrs_get_format_fn(&input.attrs) {
+            Diagnostic::spanned(
+                format_fn.span(),
+                Level::Error,
+                String::from("`#[format(fn = ...)]` only works on variants"),
+            )
+            .help(String::from(
+                "move it to the variants, or use `#[format = \"...\"]` with `{message}`",
+            ))
+            .emit()
+        }
+        for variant in &data.variants {
+            // only variants with a single value to display use `display`
+            // and to be converted from
+            let (allowed, field_allowed): (&[&str], &[&str]) = match &variant.fields {
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => (
+                    &[
+                        "code",
+                        "display",
+                        "format",
+                        "format_alt",
+                        "from",
+                        "no_from",
+                        "transparent",
+                    ],
+                    &["backtrace", "display", "from", "no_from"],
+                ),
+                Fields::Unit if variant.discriminant.is_some() => (
+                    &["code", "display", "format", "format_alt", "transparent"],
+                    &[],
+                ),
+                _ => {
+                    // only the list form converts other types into a field of these variants
+                    if let Some(attr) = variant.attrs.iter().find(
+                        |attr| matches!(&attr.meta, Meta::Path(path) if path.is_ident("from")),
+                    ) {
+                        Diagnostic::spanned(
+                            attr.span(),
+                            Level::Error,
+                            format!("`#[from]` has no effect on variant `{}`", variant.ident),
+                        )
+                        .help(String::from(
+                            "put `#[from]` on the field to convert from, or use `#[from(Type, via = Intermediate)]`",
+                        ))
+                        .emit()
+                    }
+
+                    (
+                        &["code", "format", "format_alt", "from", "transparent"],
+                        &["backtrace", "display", "from"],
+                    )
+                }
+            };
+
+            validate_attrs(&variant.attrs, ATTRIBUTES, allowed, "this variant");
+            for field in &variant.fields {
+                validate_attrs(&field.attrs, ATTRIBUTES, field_allowed, "this field");
+            }
+        }
+        let mut variants = data
+            .variants
+            .iter()
+            .map(EnumVariant::from)
+            .collect::<Vec<_>>();
+
+        for variant in &variants {
+            check_backtrace_fields(variant.fields());
+        }
+        let types = check_from_fields(&mut variants);
+        let mut from_vias = data
+            .variants
+            .iter()
+            .map(|variant| attrs_get_from_via(&variant.attrs))
+            .collect::<Vec<_>>();
+
+        check_from_via(&variants, &mut from_vias, types);
+
+        let format_fns = data
+            .variants
+            .iter()
+            .map(|variant| attrs_get_format_fn(&variant.attrs))
+            .collect::<Vec<_>>();
+
+        let defaults = attrs_get_defaults(&input.attrs);
+
+        for (variant, format_fn) in variants.iter_mut().zip(&format_fns) {
+            if format_fn.is_none() {
+                if defaults.humanize {
+                    variant.humanize_missing_format();
+                }
+            } else if variant.format_alt().is_some() {
+                Diagnostic::spanned(
+                    variant.ident().span(),
+                    Level::Error,
+                    format!(
+                        "`#[format(fn = ...)]` on variant `{}` cannot be combined with `format_alt`",
+                        variant.ident()
+                    ),
+                )
+                .help(String::from(
+                    "check `f.alternate()` in the function instead of using `format_alt`",
+                ))
+                .emit()
+            }
+        }
 
         Self {
+            codes: get_codes(&data.variants),
             ident: &input.ident,
-            format: attrs_get_lit_str(&input.attrs, "format").ok(),
+            format: attrs_get_format(&input.attrs, "format"),
+            format_alt: attrs_get_format(&input.attrs, "format_alt"),
+            format_fns,
+            from_vias,
             generics: &input.generics,
+            kind: attrs_contains(&input.attrs, "kind"),
             variants,
+            vis: &input.vis,
         }
     }
 }
 impl ToTokens for EnumError<'_> {
     fn to_tokens(&self, output: &mut TokenStream2) {
         output.extend(
-            [Self::to_display_impl, Self::to_from_impls]
-                .into_iter()
-                .map(|convertor| (convertor)(self))
-                .collect::<TokenStream2>(),
+            [
+                Self::to_display_impl,
+                Self::to_from_impls,
+                Self::to_inherent_impl,
+                Self::to_kind_enum,
+            ]
+            .into_iter()
+            .map(|convertor| (convertor)(self))
+            .collect::<TokenStream2>(),
         );
     }
 }
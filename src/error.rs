@@ -0,0 +1,175 @@
+use crate::{
+    common::{as_dyn_error_trait, attrs_contains, check_source_fields, validate_attrs},
+    enum_error::{backtrace_body, EnumVariant},
+    prelude::*,
+    struct_error::StructErrorVariant,
+};
+
+/// The attributes [Error] reads
+const ATTRIBUTES: &[&str] = &["no_source", "source"];
+
+/// The items [Error] can be derived for.
+pub enum ErrorData<'a> {
+    Enum(Vec<EnumVariant<'a>>),
+    Struct(StructErrorVariant<'a>),
+    /// Unions cannot have a source
+    Union,
+}
+
+pub struct Error<'a> {
+    ident: &'a Ident,
+    generics: &'a Generics,
+    data: ErrorData<'a>,
+}
+impl Error<'_> {
+    /// Creates the [std::error::Error::source] method if any field is a source.
+    fn to_source_fn(&self) -> Option<TokenStream2> {
+        let body = match &self.data {
+            ErrorData::Enum(variants) => {
+                if !variants.iter().any(EnumVariant::has_source) {
+                    return None;
+                }
+                let match_arms = variants
+                    .iter()
+                    .map(EnumVariant::to_source_match_arm)
+                    .collect::<TokenStream2>();
+
+                quote! {
+                    match self {
+                        #match_arms
+                    }
+                }
+            }
+            ErrorData::Struct(variant) => variant.to_source_body()?,
+            ErrorData::Union => return None,
+        };
+
+        let as_dyn_error = as_dyn_error_trait();
+
+        Some(quote! {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                #as_dyn_error
+
+                #body
+            }
+        })
+    }
+
+    /// Creates the [std::error::Error::provide] method, which provides the backtrace, if any field is a backtrace.
+    ///
+    /// This needs the nightly `provide` feature.
+    fn to_provide_fn(&self) -> Option<TokenStream2> {
+        if !cfg!(feature = "provide") {
+            return None;
+        }
+        let body = match &self.data {
+            ErrorData::Enum(variants) => backtrace_body(variants)?,
+            ErrorData::Struct(variant) => variant.to_backtrace_body()?,
+            ErrorData::Union => return None,
+        };
+
+        Some(quote! {
+            fn provide<'a>(&'a self, request: &mut std::error::Request<'a>) {
+                let backtrace: Option<&std::backtrace::Backtrace> = #body;
+
+                if let Some(backtrace) = backtrace {
+                    request.provide_ref::<std::backtrace::Backtrace>(backtrace);
+                }
+            }
+        })
+    }
+}
+impl<'a> From<&'a DeriveInput> for Error<'a> {
+    fn from(input: &'a DeriveInput) -> Self {
+        validate_attrs(&input.attrs, ATTRIBUTES, &[], "this item");
+        let data = match &input.data {
+            Data::Enum(data) => {
+                for variant in &data.variants {
+                    // the attributes of variants wrapping a single value apply to that value
+                    let single = matches!(
+                        &variant.fields,
+                        Fields::Unnamed(fields) if fields.unnamed.len() == 1
+                    );
+                    // `#[from(...)]` on the variant converts other types, so only a bare `#[from]` counts
+                    let variant_from = variant.attrs.iter().any(
+                        |attr| matches!(&attr.meta, Meta::Path(path) if path.is_ident("from")),
+                    );
+                    let from = single
+                        && (variant_from
+                            || variant
+                                .fields
+                                .iter()
+                                .any(|field| attrs_contains(&field.attrs, "from")));
+                    let allowed: &[&str] = match (single, from) {
+                        (true, true) => &["no_source", "source"],
+                        (true, false) => &["source"],
+                        (false, _) => &[],
+                    };
+
+                    validate_attrs(&variant.attrs, ATTRIBUTES, allowed, "this variant");
+                    for field in &variant.fields {
+                        // `no_source` also opts out of the source of a `From` conversion
+                        let allowed: &[&str] = if from || attrs_contains(&field.attrs, "from") {
+                            &["no_source", "source"]
+                        } else {
+                            &["source"]
+                        };
+
+                        validate_attrs(&field.attrs, ATTRIBUTES, allowed, "this field");
+                    }
+                }
+                let variants = data
+                    .variants
+                    .iter()
+                    .map(EnumVariant::from)
+                    .collect::<Vec<_>>();
+
+                for variant in &variants {
+                    check_source_fields(variant.fields());
+                }
+                ErrorData::Enum(variants)
+            }
+            Data::Struct(data) => {
+                let from = attrs_contains(&input.attrs, "from");
+
+                for field in &data.fields {
+                    // `no_source` also opts out of the source of a `From` conversion
+                    let allowed: &[&str] = if from || attrs_contains(&field.attrs, "from") {
+                        &["no_source", "source"]
+                    } else {
+                        &["source"]
+                    };
+
+                    validate_attrs(&field.attrs, ATTRIBUTES, allowed, "this field");
+                }
+                let variant = StructErrorVariant::new(&input.attrs, &data.fields);
+
+                check_source_fields(variant.fields());
+                ErrorData::Struct(variant)
+            }
+            Data::Union(_) => ErrorData::Union,
+        };
+
+        Self {
+            ident: &input.ident,
+            generics: &input.generics,
+            data,
+        }
+    }
+}
+impl ToTokens for Error<'_> {
+    fn to_tokens(&self, output: &mut TokenStream2) {
+        let ident = self.ident;
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let source_fn = self.to_source_fn();
+        let provide_fn = self.to_provide_fn();
+
+        output.extend(quote! {
+            #[automatically_derived]
+            impl #impl_generics std::error::Error for #ident #ty_generics #where_clause {
+                #source_fn
+                #provide_fn
+            }
+        });
+    }
+}
@@ -0,0 +1,424 @@
+//! Parsing of the strings in `format` attributes
+
+use {
+    crate::{common::similar, prelude::*},
+    std::ops::Range,
+};
+
+/// What a placeholder refers to before any member access.
+#[derive(Clone)]
+pub enum ArgumentRoot {
+    /// `{0}`, or the position of `{}`
+    Index(usize),
+    /// `{name}`
+    Name(String),
+}
+impl ArgumentRoot {
+    /// Check if the root refers to the field accessed with `member`.
+    ///
+    /// `arg_{i}` is still accepted for unnamed fields.
+    pub fn matches(&self, member: &Member) -> bool {
+        match (self, member) {
+            (Self::Index(i), Member::Unnamed(index)) => *i == index.index as usize,
+            (Self::Name(name), Member::Named(ident)) => ident == name,
+            (Self::Name(name), Member::Unnamed(index)) => name
+                .strip_prefix("arg_")
+                .and_then(|i| i.parse::<u32>().ok())
+                .is_some_and(|i| i == index.index),
+            (Self::Index(_), Member::Named(_)) => false,
+        }
+    }
+}
+
+/// The argument of a placeholder, such as `0` in `{0}` or `path.len()` in `{path.len():?}`.
+#[derive(Clone)]
+pub struct Argument {
+    pub root: ArgumentRoot,
+    /// Member access and method calls after the root, such as `.kind`
+    pub members: Option<TokenStream2>,
+    /// The source text, used in errors
+    text: String,
+    /// Identical arguments have the same key
+    key: String,
+}
+
+/// A width or precision in a format spec that refers to an argument, such as `width$` or `.*`.
+#[derive(Clone)]
+struct Count {
+    argument: Argument,
+    /// Where the reference is in the spec, including the `$`
+    range: Range<usize>,
+}
+
+/// A piece of a parsed format string.
+#[derive(Clone)]
+enum Piece {
+    /// Text that is kept as is, with braces still escaped
+    Literal(String),
+    Placeholder {
+        argument: Argument,
+        /// Everything after `:`
+        spec: Option<String>,
+        /// The arguments `spec` refers to
+        counts: Vec<Count>,
+        /// Where the placeholder is in the value of the string
+        range: Range<usize>,
+    },
+}
+
+/// A string from a `format` attribute with its placeholders parsed.
+#[derive(Clone)]
+pub struct FormatString {
+    lit: LitStr,
+    pieces: Vec<Piece>,
+}
+impl FormatString {
+    /// `"{}"`, used when a single value is formatted without a `format` attribute
+    pub fn positional() -> Self {
+        Self::parse(&LitStr::new("{}", Span::call_site())).unwrap()
+    }
+
+    /// A string without placeholders that writes `text` as is.
+    ///
+    /// It stands in for a string that could not be used, so the rest of the implementation can still be generated.
+    pub fn literal(text: &str, span: Span) -> Self {
+        Self {
+            lit: LitStr::new(text, span),
+            pieces: vec![Piece::Literal(text.replace('{', "{{").replace('}', "}}"))],
+        }
+    }
+
+    pub fn parse(lit: &LitStr) -> syn::Result<Self> {
+        let value = lit.value();
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut next_index = 0;
+        let mut chars = value.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek().is_some_and(|(_, next)| *next == c) => {
+                    chars.next();
+                    literal.push(c);
+                    literal.push(c);
+                }
+                '{' => {
+                    let end = chars
+                        .by_ref()
+                        .find(|(_, c)| *c == '}')
+                        .map(|(i, _)| i)
+                        .ok_or_else(|| {
+                            syn::Error::new(
+                                span_of(lit, start..value.len()),
+                                "unmatched `{` in format string",
+                            )
+                        })?;
+                    let range = start..end + 1;
+                    let (argument, spec) = split_spec(&value[start + 1..end]);
+                    let error = |message| syn::Error::new(span_of(lit, range.clone()), message);
+                    // `.*` takes the argument before the value
+                    let counts = spec
+                        .map(|spec| parse_counts(spec, &mut next_index))
+                        .transpose()
+                        .map_err(error)?
+                        .unwrap_or_default();
+                    let argument = parse_argument(argument, &mut next_index).map_err(error)?;
+
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Placeholder {
+                        argument,
+                        spec: spec.map(String::from),
+                        counts,
+                        range,
+                    });
+                }
+                '}' => {
+                    return Err(syn::Error::new(
+                        span_of(lit, start..start + 1),
+                        "unmatched `}` in format string",
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self {
+            lit: lit.clone(),
+            pieces,
+        })
+    }
+
+    /// The string as it is written in the attribute
+    pub fn value(&self) -> String {
+        self.lit.value()
+    }
+
+    /// Creates the arguments of [write] after the formatter.
+    ///
+    /// Every placeholder, and every argument a width or precision refers to, becomes a named argument with the value `resolve` returns for it.
+    /// A placeholder `resolve` does not know is reported with a suggestion from `known`, the names it accepts, and left out.
+    pub fn to_args(
+        &self,
+        known: &[String],
+        mut resolve: impl FnMut(&Argument) -> Option<TokenStream2>,
+    ) -> TokenStream2 {
+        let mut string = String::new();
+        // the key, name and value of every argument
+        let mut args = Vec::<(String, Ident, TokenStream2)>::new();
+        let mut name_of = |args: &mut Vec<(String, Ident, TokenStream2)>,
+                           argument: &Argument,
+                           range: &Range<usize>,
+                           count: bool| {
+            if let Some((_, name, _)) = args.iter().find(|(key, ..)| *key == argument.key) {
+                return Some(name.clone());
+            }
+            let Some(value) = resolve(argument) else {
+                self.unknown_argument(argument, range.clone(), known).emit();
+                return None;
+            };
+            let name = format_ident!("__arg{}", args.len());
+            // counts have to be a `usize`, while fields of variants are bound by reference
+            let value = if count {
+                quote! { *std::borrow::Borrow::<usize>::borrow(&#value) }
+            } else {
+                value
+            };
+            args.push((argument.key.clone(), name.clone(), value));
+
+            Some(name)
+        };
+
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => string.push_str(literal),
+                Piece::Placeholder {
+                    argument,
+                    spec,
+                    counts,
+                    range,
+                } => {
+                    let len = args.len();
+                    let count_names = counts
+                        .iter()
+                        .map(|count| name_of(&mut args, &count.argument, range, true))
+                        .collect::<Vec<_>>();
+                    let name = name_of(&mut args, argument, range, false);
+                    let (Some(name), Some(count_names)) =
+                        (name, count_names.into_iter().collect::<Option<Vec<_>>>())
+                    else {
+                        // the placeholder is left out, so its other arguments would be unused
+                        args.truncate(len);
+                        continue;
+                    };
+
+                    string.push('{');
+                    string.push_str(&name.to_string());
+                    if let Some(spec) = spec {
+                        let mut end = 0;
+
+                        string.push(':');
+                        for (count, name) in counts.iter().zip(count_names) {
+                            string.push_str(&spec[end..count.range.start]);
+                            string.push_str(&format!("{}$", name));
+                            end = count.range.end;
+                        }
+                        string.push_str(&spec[end..]);
+                    }
+                    string.push('}');
+                }
+            }
+        }
+        let string = LitStr::new(&string, self.lit.span());
+
+        let args = args
+            .iter()
+            .map(|(_, name, value)| quote! { #name = #value });
+
+        quote! {
+            #string #(, #args)*
+        }
+    }
+
+    /// Creates the error for a placeholder that does not refer to anything.
+    fn unknown_argument(
+        &self,
+        argument: &Argument,
+        range: Range<usize>,
+        known: &[String],
+    ) -> Diagnostic {
+        let root = match &argument.root {
+            ArgumentRoot::Index(i) => i.to_string(),
+            ArgumentRoot::Name(name) => name.clone(),
+        };
+        let span = span_of(&self.lit, range);
+
+        if known.contains(&root) {
+            return Diagnostic::spanned(
+                span,
+                Level::Error,
+                format!("`{}` cannot be formatted here", argument.text),
+            )
+            .help(format!("use `{{{}}}` instead", root));
+        }
+        let diagnostic = Diagnostic::spanned(
+            span,
+            Level::Error,
+            format!("there is no field `{}` to format", root),
+        );
+
+        match similar(&root, known) {
+            Some(similar) => diagnostic.help(format!("did you mean `{}`?", similar)),
+            None if known.is_empty() => {
+                diagnostic.help(String::from("nothing can be formatted here"))
+            }
+            None => diagnostic.help(format!(
+                "available fields are {}",
+                known
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Split the contents of a placeholder at the `:` starting the format spec, ignoring paths such as `a::b`.
+fn split_spec(placeholder: &str) -> (&str, Option<&str>) {
+    let bytes = placeholder.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b':' if bytes.get(i + 1) == Some(&b':') => i += 2,
+            b':' => return (&placeholder[..i], Some(&placeholder[i + 1..])),
+            _ => i += 1,
+        }
+    }
+
+    (placeholder, None)
+}
+
+/// Parses the arguments a format spec refers to for its width and precision, where `next_index` is the position of the next `{}`.
+fn parse_counts(spec: &str, next_index: &mut usize) -> Result<Vec<Count>, String> {
+    let mut counts = Vec::new();
+    let mut chars = spec.chars();
+    // fill and alignment
+    let mut i = match (chars.next(), chars.next()) {
+        (Some(fill), Some('<' | '^' | '>')) => fill.len_utf8() + 1,
+        (Some('<' | '^' | '>'), _) => 1,
+        _ => 0,
+    };
+
+    if spec[i..].starts_with(['+', '-']) {
+        i += 1;
+    }
+    if spec[i..].starts_with('#') {
+        i += 1;
+    }
+    // `0$` is a width, not the zero flag
+    if spec[i..].starts_with('0') && !spec[i + 1..].starts_with('$') {
+        i += 1;
+    }
+    i = parse_count(spec, i, &mut counts)?;
+    if spec[i..].starts_with(".*") {
+        counts.push(Count {
+            argument: count_argument(parse_argument("", next_index)?),
+            range: i + 1..i + 2,
+        });
+    } else if spec[i..].starts_with('.') {
+        parse_count(spec, i + 1, &mut counts)?;
+    }
+
+    Ok(counts)
+}
+
+/// Reads the width or precision at `start` of `spec`, adding it to `counts` if it refers to an argument.
+///
+/// Returns where the width or precision ends.
+fn parse_count(spec: &str, start: usize, counts: &mut Vec<Count>) -> Result<usize, String> {
+    let end = spec[start..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(spec.len(), |len| start + len);
+
+    if end == start || !spec[end..].starts_with('$') {
+        return Ok(end);
+    }
+    counts.push(Count {
+        argument: count_argument(parse_argument(&spec[start..end], &mut 0)?),
+        range: start..end + 1,
+    });
+
+    Ok(end + 1)
+}
+
+/// Gives an argument used as a count its own key, since it is passed as a `usize` instead of by reference.
+fn count_argument(mut argument: Argument) -> Argument {
+    argument.key.push('$');
+    argument
+}
+
+/// Parses the argument of a placeholder, where `next_index` is the position of the next `{}`.
+fn parse_argument(text: &str, next_index: &mut usize) -> Result<Argument, String> {
+    if text.is_empty() {
+        *next_index += 1;
+
+        return Ok(Argument {
+            root: ArgumentRoot::Index(*next_index - 1),
+            members: None,
+            text: String::new(),
+            key: (*next_index - 1).to_string(),
+        });
+    }
+
+    let (root, members) = match text.find('.') {
+        Some(i) => (&text[..i], Some(&text[i..])),
+        None => (text, None),
+    };
+    let root = if !root.is_empty() && root.bytes().all(|byte| byte.is_ascii_digit()) {
+        ArgumentRoot::Index(
+            root.parse()
+                .map_err(|_| format!("invalid argument `{}` in format string", text))?,
+        )
+    } else if syn::parse_str::<Ident>(root).is_ok() {
+        ArgumentRoot::Name(String::from(root))
+    } else {
+        return Err(format!("invalid argument `{}` in format string", text));
+    };
+    let members = members
+        .map(|members| {
+            // members have to be valid after a variable
+            syn::parse_str::<Expr>(&format!("x{}", members))
+                .and_then(|_| syn::parse_str::<TokenStream2>(members))
+                .map_err(|_| format!("invalid member access `{}` in format string", text))
+        })
+        .transpose()?;
+
+    Ok(Argument {
+        root,
+        members,
+        text: String::from(text),
+        key: String::from(text),
+    })
+}
+
+/// Get the span of `range` inside the value of `lit`.
+///
+/// This falls back to the whole literal where the compiler cannot create the span or the range cannot be mapped because of escapes.
+fn span_of(lit: &LitStr, range: Range<usize>) -> Span {
+    let token = lit.token();
+    let source = token.to_string();
+
+    // only plain strings without escapes have the same layout as their value
+    source
+        .strip_prefix('"')
+        .and_then(|source| source.strip_suffix('"'))
+        .filter(|source| *source == lit.value())
+        .and_then(|_| token.subspan(range.start + 1..range.end + 1))
+        .unwrap_or_else(|| lit.span())
+}
@@ -1,73 +1,796 @@
 mod common;
 mod enum_error;
+mod error;
+mod format_string;
 mod prelude;
 mod struct_error;
 
-use {enum_error::EnumError, prelude::*, struct_error::StructError};
-
-/// Saves you from typing ```impl std::error::Error for FooError {}```.
-/// # Examples
-/// ```
-/// use error_proc_macros::{Error, StructError};
-/// #[derive(Debug, Error, StructError)]
-/// #[format = "scary error"]
-/// pub struct MyError;
-/// let my_error: Box<dyn std::error::Error> = Box::new(MyError {});
-/// ```
-#[proc_macro_derive(Error)]
+use {
+    common::derive_output, enum_error::EnumError, error::Error, prelude::*,
+    struct_error::StructError,
+};
+
+/**
+Saves you from typing ```impl std::error::Error for FooError {}```.
+
+# Examples
+```
+use error_proc_macros::{Error, StructError};
+#[derive(Debug, Error, StructError)]
+#[format = "scary error"]
+pub struct MyError;
+let my_error: Box<dyn std::error::Error> = Box::new(MyError {});
+```
+
+# Attributes
+## `source`
+Marks the field returned by [std::error::Error::source].
+It can be used on structs and on the fields of any enum variant, the field must implement [std::error::Error] and be `'static`.
+```
+use {
+    error_proc_macros::{EnumError, Error, StructError},
+    std::{error::Error, num::ParseIntError},
+};
+
+#[derive(Debug, Error, StructError)]
+#[format = "failed to read config"]
+struct ConfigError {
+    #[source]
+    cause: ParseIntError,
+}
+
+#[derive(Debug, EnumError, Error)]
+enum CliError {
+    #[format = "invalid argument {index}"]
+    Argument {
+        index: usize,
+        #[source]
+        cause: ParseIntError,
+    },
+    #[format = "no arguments"]
+    Empty,
+}
+
+let cause = "foo".parse::<u8>().unwrap_err();
+let error = ConfigError { cause: cause.clone() };
+assert_eq!(error.source().unwrap().to_string(), cause.to_string());
+
+let error = CliError::Argument { index: 1, cause: cause.clone() };
+assert_eq!(error.source().unwrap().to_string(), cause.to_string());
+assert!(CliError::Empty.source().is_none());
+```
+
+Only one field can be the source.
+```compile_fail
+use {
+    error_proc_macros::{Error, StructError},
+    std::{io, num::ParseIntError},
+};
+
+#[derive(Debug, Error, StructError)]
+#[format = "failed to read config"]
+struct ConfigError {
+    #[source]
+    cause: ParseIntError,
+    // error: there can only be one source field
+    #[source]
+    io: io::Error,
+}
+```
+
+Fields marked with `from`, including single type enum variants with `#[from]`, are also used as the source.
+Single type variants that only wrap their value are not, since the value does not have to be an error, but can be marked with `#[source]`.
+```
+use {
+    error_proc_macros::Error,
+    std::{
+        error::Error,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+#[derive(Debug, Error)]
+enum StatusError {
+    Code(u32),
+    Other,
+}
+
+impl Display for StatusError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "status {}", code),
+            Self::Other => write!(f, "unknown status"),
+        }
+    }
+}
+
+assert!(StatusError::Code(404).source().is_none());
+assert_eq!(StatusError::Other.to_string(), "unknown status");
+```
+
+Boxed trait objects such as `Box<dyn Error + Send + Sync>` can be a source as well.
+```
+use {
+    error_proc_macros::{Error, StructError},
+    std::error::Error,
+};
+
+#[derive(Debug, Error, StructError)]
+#[format = "request failed"]
+struct RequestError {
+    #[source]
+    cause: Box<dyn Error + Send + Sync>,
+}
+
+let error = RequestError { cause: "timed out".into() };
+assert_eq!(error.source().unwrap().to_string(), "timed out");
+```
+
+## `no_source`
+Stops a field marked with `from`, or a single type enum variant with `#[from]`, from being used as the source.
+```
+use {
+    error_proc_macros::{EnumError, Error},
+    std::{error::Error, num::ParseIntError},
+};
+
+#[derive(Debug, EnumError, Error)]
+enum ParseError {
+    #[from]
+    Int(ParseIntError),
+    #[from]
+    #[no_source]
+    Other(String),
+}
+
+let cause = "foo".parse::<u8>().unwrap_err();
+assert_eq!(
+    ParseError::from(cause.clone()).source().unwrap().to_string(),
+    cause.to_string()
+);
+assert!(ParseError::from(String::from("bar")).source().is_none());
+```
+
+## `backtrace`
+With the `provide` feature, a backtrace field, as described for [EnumError](derive.EnumError.html#backtrace), is given to [std::error::Error::provide].
+This needs nightly and `#![feature(error_generic_member_access)]` in the crate using the derive.
+*/
+#[proc_macro_derive(Error, attributes(backtrace, no_source, source, transparent))]
+#[proc_macro_error]
 pub fn error(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let ident = &input.ident;
-    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
-    quote! {
-        impl #impl_generics std::error::Error for #ident #ty_generics #where_clause {}
-    }
-    .into()
+    derive_output(Error::from(&input).into_token_stream())
 }
 
 /**
 Creates an error type from an enum.
 
 # Attributes
+## `backtrace`
+A field of type [Backtrace](std::backtrace::Backtrace), or a field marked with `#[backtrace]`, holds the backtrace of the error.
+Generated [From] implementations capture it, and `fn backtrace(&self) -> Option<&Backtrace>` gives it for the variants that have one.
+```
+use {
+    error_proc_macros::EnumError,
+    std::{backtrace::Backtrace, num::ParseIntError},
+};
+
+#[derive(Debug, EnumError)]
+enum Error {
+    #[format = "invalid number {source}"]
+    Number {
+        #[from]
+        source: ParseIntError,
+        backtrace: Backtrace,
+    },
+    #[format = "empty"]
+    Empty,
+}
+
+let error = Error::from("foo".parse::<u8>().unwrap_err());
+assert!(error.backtrace().is_some());
+assert!(Error::Empty.backtrace().is_none());
+```
+
+## `code`
+Gives a variant a stable error code, as a string or an integer literal.
+This generates `fn code(&self) -> &'static str` and `const CODES: &[&str]` with the code of every variant, which then need a code and must be unique.
+`#[code]` without a value uses an integer discriminant and `{code}` can be used in the `format` of the enum.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+#[format = "error[{code}]: {}"]
+enum Error {
+    #[code = "E0001"]
+    #[format = "file not found"]
+    NotFound,
+    #[code = 2]
+    #[format = "permission denied"]
+    PermissionDenied,
+}
+
+#[derive(EnumError)]
+enum ExitCode {
+    #[code]
+    Failure = 1,
+}
+
+assert_eq!(Error::NotFound.code(), "E0001");
+assert_eq!(Error::CODES, ["E0001", "2"]);
+assert_eq!(Error::PermissionDenied.to_string(), "error[2]: permission denied");
+assert_eq!(ExitCode::Failure.code(), "1");
+```
+
+Codes are checked when the error is derived.
+```compile_fail
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+enum Error {
+    #[code = "E0001"]
+    #[format = "file not found"]
+    NotFound,
+    // error: duplicate code `E0001`
+    #[code = "E0001"]
+    #[format = "permission denied"]
+    PermissionDenied,
+}
+```
+```compile_fail
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+enum Error {
+    #[code = "E0001"]
+    #[format = "file not found"]
+    NotFound,
+    // error: variant `PermissionDenied` has no `code`
+    #[format = "permission denied"]
+    PermissionDenied,
+}
+```
+
 ## `display`
-Insert a closure to give a field formatting.
+Insert a closure or function to give a field formatting.
+It can be written as `#[display(...)]` or inside a string as `#[display = "..."]`, since rust only accepts literals after `=`.
 ```
 use {
     error_proc_macros::EnumError,
     std::path::Path,
 };
 
+fn quoted(name: &&str) -> String {
+    format!("`{}`", name)
+}
+
 #[derive(EnumError)]
 enum PathError<'a> {
     #[format = "path `{}` does not exist"]
     #[display = "|path: &'a Path| path.display()"]
     NonExistant(&'a Path),
+    #[format = "{name} is not a directory"]
+    NotDirectory {
+        #[display(quoted)]
+        name: &'a str,
+    },
+    #[format = "path `{}` is a symlink"]
+    #[display(|path: &'a Path| path.display())]
+    Symlink(&'a Path),
 }
 
 assert_eq!(
     PathError::NonExistant(Path::new("foo.txt")).to_string(),
     String::from("path `foo.txt` does not exist")
 );
+assert_eq!(
+    PathError::NotDirectory { name: "foo" }.to_string(),
+    String::from("`foo` is not a directory")
+);
+assert_eq!(
+    PathError::Symlink(Path::new("foo")).to_string(),
+    String::from("path `foo` is a symlink")
+);
+```
+
+### `with`
+`#[display(with = ...)]` calls a function taking a reference to the field, so a shared conversion does not need a closure per field.
+```
+use {
+    error_proc_macros::EnumError,
+    std::path::{Path, PathBuf},
+};
+
+#[derive(EnumError)]
+enum PathError {
+    #[format = "cannot copy `{arg_0}` to `{arg_1}`"]
+    Copy(
+        #[display(with = Path::display)] PathBuf,
+        #[display(with = Path::display)] PathBuf,
+    ),
+    #[format = "path `{}` does not exist"]
+    #[display(with = Path::display)]
+    NonExistant(PathBuf),
+}
+
+assert_eq!(
+    PathError::Copy(PathBuf::from("foo"), PathBuf::from("bar")).to_string(),
+    String::from("cannot copy `foo` to `bar`")
+);
+```
+
+### `optional`
+`#[display(optional = "...")]` on an [Option] field writes the string with the value when it is [Some], and nothing when it is [None].
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "unexpected token{line}"]
+    Token {
+        #[display(optional = " at line {}")]
+        line: Option<u32>,
+    },
+}
+
+assert_eq!(Error::Token { line: Some(3) }.to_string(), "unexpected token at line 3");
+assert_eq!(Error::Token { line: None }.to_string(), "unexpected token");
+```
+
+### `join`
+`#[display(join = ", ")]` writes the items of a collection with the separator between them.
+`with` is then called with a reference to every item and `max` limits how many items are written.
+```
+use error_proc_macros::EnumError;
+
+fn quoted(key: &&str) -> String {
+    format!("`{}`", key)
+}
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "missing keys: {}"]
+    #[display(join = ", ")]
+    Missing(Vec<&'static str>),
+    #[format = "unknown keys: {}"]
+    #[display(join = ", ", with = quoted, max = 2)]
+    Unknown(Vec<&'static str>),
+    #[format = "duplicate keys{}"]
+    #[display(join = ", ", max = 0)]
+    Duplicate(Vec<&'static str>),
+    #[format = "keys {count}{f}"]
+    Counted {
+        #[display(join = ", ")]
+        count: Vec<u8>,
+        #[display(optional = " <{}>")]
+        f: Option<u8>,
+    },
+}
+
+assert_eq!(Error::Missing(vec!["a", "b"]).to_string(), "missing keys: a, b");
+assert_eq!(
+    Error::Unknown(vec!["a", "b", "c", "d"]).to_string(),
+    "unknown keys: `a`, `b`, \u{2026} (+2 more)"
+);
+assert_eq!(Error::Duplicate(vec!["a", "b"]).to_string(), "duplicate keys\u{2026} (+2 more)");
+assert_eq!(
+    Error::Counted { count: vec![1, 2], f: Some(3) }.to_string(),
+    "keys 1, 2 <3>"
+);
+```
+
+## `error_defaults`
+`#[error_defaults(humanize)]` on the enum gives variants without `format` a message made from their name and fields.
+Fields use [Debug] unless they have a `display` and sources are left out.
+Single type variants still write their value without `format`.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+#[error_defaults(humanize)]
+enum FsError {
+    FileNotFound { path: &'static str },
+    IOError { code: i32 },
+    #[format = "permission denied"]
+    PermissionDenied,
+    Busy,
+}
+
+assert_eq!(
+    FsError::FileNotFound { path: "foo.txt" }.to_string(),
+    "file not found (path: \"foo.txt\")"
+);
+assert_eq!(FsError::IOError { code: 5 }.to_string(), "io error (code: 5)");
+assert_eq!(FsError::Busy.to_string(), "busy");
 ```
 
 ## `format`
 Applies formatting.
 
 ### Argument access
-| Variant Type   | Argument access |
-| -------------- | --------------- |
-| Single tuple   | `{}`            |
-| Multiple tuple | `arg_{i}`       |
-| Struct-like    | field name      |
-| Unit           | inaccessable    |
+| Variant Type   | Argument access         |
+| -------------- | ----------------------- |
+| Single tuple   | `{}` or `{0}`           |
+| Multiple tuple | `{0}`, `{1}`, ...       |
+| Struct-like    | field name              |
+| Discriminant   | `{}` or `{0}`           |
+| Unit           | inaccessable            |
+
+Arguments can be followed by field access and method calls, such as `{0.kind}` or `{name.len()}`, which skip `display`.
+Format specs work as usual, with widths and precisions such as `{name:>width$}` taken from other fields, and `arg_{i}` is still accepted for tuple fields.
+```
+use {
+    error_proc_macros::EnumError,
+    std::num::ParseIntError,
+};
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "expected {1} arguments, found {0}"]
+    Count(usize, usize),
+    #[format = "invalid port {port:?}: {source.kind():?}"]
+    Port { port: String, source: ParseIntError },
+    #[format = "{name:>width$}|"]
+    Column { name: String, width: usize },
+}
+
+#[derive(EnumError)]
+enum ExitCode {
+    #[format = "exit code {:#04x}"]
+    Failure = 1,
+}
+
+let source = "foo".parse::<u16>().unwrap_err();
+assert_eq!(Error::Count(3, 2).to_string(), "expected 2 arguments, found 3");
+assert_eq!(
+    Error::Port { port: String::from("foo"), source }.to_string(),
+    "invalid port \"foo\": InvalidDigit"
+);
+assert_eq!(ExitCode::Failure.to_string(), "exit code 0x01");
+assert_eq!(Error::Column { name: String::from("ab"), width: 5 }.to_string(), "   ab|");
+```
+
+Fields can have any name, a field named `f` is not confused with the formatter.
+```
+use {error_proc_macros::EnumError, std::fmt::Formatter};
+
+fn frame(f: &u8, out: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(out, "frame {}", f)
+}
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "field {f}"]
+    Field { f: u8 },
+    #[format(fn = frame)]
+    Frame { f: u8 },
+}
+
+assert_eq!(Error::Field { f: 1 }.to_string(), "field 1");
+assert_eq!(Error::Frame { f: 2 }.to_string(), "frame 2");
+```
+
+Placeholders are checked when the error is derived, an unknown field is reported on the string with the closest field name.
+```compile_fail
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+enum Error {
+    // error: there is no field `nmae` to format, help: did you mean `name`?
+    #[format = "{nmae} not found"]
+    NotFound { name: String },
+}
+```
+
+### Format function
+`#[format(fn = path)]` calls a function with a reference to every field and the formatter, for messages that need logic.
+The function checks `f.alternate()` instead of using `format_alt`, which also works inside the format of the enum.
+```
+use {error_proc_macros::EnumError, std::fmt::Formatter};
+
+fn missing(count: &usize, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match count {
+        1 => write!(f, "1 file is missing"),
+        count if f.alternate() => write!(f, "{} files are missing, check the paths", count),
+        count => write!(f, "{} files are missing", count),
+    }
+}
+
+#[derive(EnumError)]
+#[format = "error: {}"]
+enum Error {
+    #[format(fn = missing)]
+    Missing(usize),
+}
+
+assert_eq!(Error::Missing(1).to_string(), "error: 1 file is missing");
+assert_eq!(Error::Missing(2).to_string(), "error: 2 files are missing");
+assert_eq!(format!("{:#}", Error::Missing(2)), "error: 2 files are missing, check the paths");
+```
+
+It only works on variants, the enum wraps their messages with a format string.
+```compile_fail
+use {error_proc_macros::EnumError, std::fmt::Formatter};
+
+fn whole(error: &Error, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "error")
+}
+
+#[derive(EnumError)]
+// error: `#[format(fn = ...)]` only works on variants
+#[format(fn = whole)]
+enum Error {
+    #[format = "not found"]
+    NotFound,
+}
+```
+
+### Enum format
+`format` on the enum wraps the message of every variant, which is `{}`, `{0}` or `{message}`.
+`{variant}` is the name of the variant.
+A width or precision on the message applies to the whole message, the same as for `optional` and `join` fields.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+#[format = "[{variant}] {message}"]
+enum Error {
+    #[format = "file not found"]
+    NotFound,
+}
+
+#[derive(EnumError)]
+#[format = "[{message:>12}]"]
+enum Padded {
+    #[format = "abc"]
+    Short,
+}
+
+assert_eq!(Error::NotFound.to_string(), "[NotFound] file not found");
+assert_eq!(Padded::Short.to_string(), "[         abc]");
+```
+
+## `format_alt`
+Used instead of `format` when formatting with `{:#}`, it can be used on the enum and on variants.
+Arguments are accessed the same way as `format`.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+#[format = "error: {}"]
+enum Error {
+    #[format = "not found"]
+    #[format_alt = "file `{path}` was not found"]
+    NotFound { path: &'static str },
+    #[format = "unknown flag"]
+    #[format_alt = "unknown flag `{alternate}`"]
+    Flag { alternate: char },
+}
+
+let error = Error::NotFound { path: "foo.txt" };
+assert_eq!(format!("{}", error), "error: not found");
+assert_eq!(format!("{:#}", error), "error: file `foo.txt` was not found");
+assert_eq!(format!("{:#}", Error::Flag { alternate: 'x' }), "error: unknown flag `x`");
+```
+
+## `from`
+Single type variants without `format` implement [From] for their value.
+`#[from]` does the same for a single type variant with `format`, or for one field of another variant, whose other fields are then filled with [Default].
+`#[no_from]` stops a single type variant from implementing [From], which is needed when two variants wrap the same type.
+```
+use {
+    error_proc_macros::EnumError,
+    std::num::{ParseFloatError, ParseIntError},
+};
+
+#[derive(Debug, EnumError)]
+enum Error {
+    Int(ParseIntError),
+    #[no_from]
+    #[format = "invalid port {}"]
+    Port(ParseIntError),
+    #[format = "invalid float on line {line}"]
+    Float {
+        #[from]
+        source: ParseFloatError,
+        line: usize,
+    },
+}
+
+let error = Error::from("foo".parse::<f32>().unwrap_err());
+assert_eq!(error.to_string(), "invalid float on line 0");
+assert!(matches!(Error::from("foo".parse::<u8>().unwrap_err()), Error::Int(_)));
+```
+
+Two variants cannot be converted from the same type.
+```compile_fail
+use {error_proc_macros::EnumError, std::num::ParseIntError};
+
+#[derive(EnumError)]
+enum Error {
+    Int(ParseIntError),
+    // error: `From` for this type is already implemented by variant `Int`
+    #[from]
+    #[format = "invalid port {}"]
+    Port(ParseIntError),
+}
+```
+
+A variant can only be converted from one field.
+```compile_fail
+use {error_proc_macros::EnumError, std::num::ParseIntError};
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "invalid port {name}"]
+    Port {
+        #[from]
+        source: ParseIntError,
+        // error: variant `Port` can only be converted from one field
+        #[from]
+        name: String,
+    },
+}
+```
+
+`#[from(Type, ...)]` on a variant implements [From] for other types, which are converted into the value, or the field marked with `#[from]`, with its own [From].
+`via = Intermediate` converts them into another type first, so errors can skip a layer of wrapping.
+```
+use {error_proc_macros::EnumError, std::io};
+
+#[derive(Debug, EnumError)]
+enum DbError {
+    Io(io::Error),
+}
+
+#[derive(Debug, EnumError)]
+enum AppError {
+    #[from(io::Error, via = DbError)]
+    Db(DbError),
+}
+
+let error = AppError::from(io::Error::other("disk full"));
+assert!(matches!(error, AppError::Db(DbError::Io(_))));
+assert_eq!(error.to_string(), "disk full");
+```
+
+These types also cannot be converted from by another variant.
+```compile_fail
+use {error_proc_macros::EnumError, std::io};
+
+#[derive(Debug, EnumError)]
+enum DbError {
+    Io(io::Error),
+}
+
+#[derive(EnumError)]
+enum AppError {
+    Io(io::Error),
+    // error: `From` for this type is already implemented by variant `Io`
+    #[from(io::Error, via = DbError)]
+    Db(DbError),
+}
+```
+
+A variant with several fields takes `#[from]` on the field instead.
+```compile_fail
+use {error_proc_macros::EnumError, std::num::ParseIntError};
+
+#[derive(EnumError)]
+enum Error {
+    // error: `#[from]` has no effect on variant `Port`
+    #[from]
+    #[format = "invalid port on line {line}"]
+    Port { source: ParseIntError, line: usize },
+}
+```
+
+## `kind`
+`#[kind]` on the enum generates a fieldless `{Enum}Kind` enum with the same variants and `fn kind(&self)`, which gives the variant without its fields.
+The kind is [Copy], [Eq], [Hash] and displays as the name of the variant.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+#[kind]
+enum Error {
+    #[format = "file `{path}` not found"]
+    NotFound { path: String },
+    #[format = "timed out"]
+    Timeout,
+}
+
+let error = Error::NotFound { path: String::from("foo.txt") };
+assert_eq!(error.kind(), ErrorKind::NotFound);
+assert_eq!(Error::Timeout.kind().to_string(), "Timeout");
+```
+
+## `transparent`
+Forwards [Display][std::fmt::Display] and [std::error::Error::source] to the value of a single type variant.
+Transparent variants ignore the `format` of the enum and cannot have their own `format` or `display`.
+[Debug] is not forwarded, derive or implement it as usual.
+```
+use {
+    error_proc_macros::{EnumError, Error},
+    std::num::ParseIntError,
+};
+
+#[derive(Debug, EnumError, Error)]
+#[format = "config error: {}"]
+enum ConfigError {
+    #[transparent]
+    Parse(ParseIntError),
+    #[format = "missing key"]
+    Missing,
+}
+
+let cause = "foo".parse::<u8>().unwrap_err();
+assert_eq!(ConfigError::from(cause.clone()).to_string(), cause.to_string());
+assert_eq!(ConfigError::Missing.to_string(), "config error: missing key");
+```
+
+# Formatter flags
+Width and precision apply to the whole message like they do for [str], alternate `{:#}` selects `format_alt` as usual.
+Transparent variants get the formatter untouched.
+```
+use error_proc_macros::EnumError;
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "not found"]
+    NotFound,
+}
+
+assert_eq!(format!("{:>11}", Error::NotFound), "  not found");
+assert_eq!(format!("{:-<11.3}", Error::NotFound), "not--------");
+```
+
+# Introspection
+Every enum gets `fn variant_name(&self) -> &'static str`, `const VARIANT_NAMES: &[&str]` and `fn format_template(&self) -> Option<&'static str>`, which gives the `format` of the variant as it is written.
+Variants that write their value without `format` give `"{}"`, variants with a format function or `transparent` give [None].
+```
+use {error_proc_macros::EnumError, std::fmt::Formatter};
+
+fn timeout(seconds: &u64, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "timed out after {}s", seconds)
+}
+
+#[derive(EnumError)]
+enum Error {
+    #[format = "file `{path}` not found"]
+    NotFound { path: String },
+    #[format(fn = timeout)]
+    Timeout(u64),
+}
+
+let error = Error::NotFound { path: String::from("foo.txt") };
+assert_eq!(error.variant_name(), "NotFound");
+assert_eq!(error.format_template(), Some("file `{path}` not found"));
+assert_eq!(Error::Timeout(3).format_template(), None);
+assert_eq!(Error::VARIANT_NAMES, ["NotFound", "Timeout"]);
+```
 */
-#[proc_macro_derive(EnumError, attributes(display, format))]
+#[proc_macro_derive(
+    EnumError,
+    attributes(
+        backtrace,
+        code,
+        display,
+        error_defaults,
+        format,
+        format_alt,
+        from,
+        kind,
+        no_from,
+        transparent
+    )
+)]
 #[proc_macro_error]
 pub fn enum_error(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    EnumError::from(&input).into_token_stream().into()
+    derive_output(EnumError::from(&input).into_token_stream())
 }
 
 /**
@@ -75,15 +798,112 @@ Creates an error type from an struct.
 
 # Attributes
 
+# `backtrace`
+Gives the struct a backtrace the same way as for [EnumError](derive.EnumError.html#backtrace).
+```
+use {
+    error_proc_macros::StructError,
+    std::{backtrace::Backtrace, num::ParseIntError},
+};
+
+#[derive(Debug, StructError)]
+#[format = "invalid port"]
+struct PortError {
+    #[from]
+    source: ParseIntError,
+    #[backtrace]
+    trace: Backtrace,
+}
+
+let error = PortError::from("foo".parse::<u16>().unwrap_err());
+assert!(error.backtrace().is_some());
+```
+
+# `code`
+Gives the struct an error code the same way as for [EnumError](derive.EnumError.html#code).
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[code = "E0042"]
+#[format = "invalid port"]
+struct PortError;
+assert_eq!(PortError.code(), "E0042");
+assert_eq!(PortError::CODES, ["E0042"]);
+```
+
+# `display`
+Gives a field formatting, the same way as for [EnumError](derive.EnumError.html#display).
+```
+use error_proc_macros::StructError;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(StructError)]
+#[format = "invalid checksum {checksum}"]
+struct ChecksumError {
+    #[display(with = hex)]
+    checksum: Vec<u8>,
+}
+assert_eq!(
+    ChecksumError { checksum: vec![0xde, 0xad] }.to_string(),
+    "invalid checksum dead"
+);
+```
+
+Fields can use `optional` as well.
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[format = "invalid config{path}"]
+struct ConfigError {
+    #[display(optional = " in `{}`")]
+    path: Option<String>,
+}
+assert_eq!(
+    ConfigError { path: Some(String::from("foo.toml")) }.to_string(),
+    "invalid config in `foo.toml`"
+);
+```
+
+On the struct itself, `display` is called with `&self` and replaces `format`, for messages that need logic across several fields.
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[display(|error: &Self| format!("expected {} item{}", error.expected, if error.expected == 1 { "" } else { "s" }))]
+struct CountError {
+    expected: usize,
+}
+assert_eq!(CountError { expected: 1 }.to_string(), "expected 1 item");
+assert_eq!(CountError { expected: 2 }.to_string(), "expected 2 items");
+```
+
+# `error_defaults`
+`#[error_defaults(humanize)]` creates a message when `format` is missing, the same way as for [EnumError](derive.EnumError.html#error_defaults).
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[error_defaults(humanize)]
+struct InvalidPort(u32);
+assert_eq!(InvalidPort(0).to_string(), "invalid port (0)");
+```
+
 # `format`
 Format can only be used on the struct itself.
 ## Argument access
-| Struct Type    | Argument access |
-| -------------- | --------------- |
-| Single tuple   | `{}`            |
-| Multiple tuple | `arg_{i}`       |
-| Named fields   | field name      |
-| Unit struct    | inaccessable    |
+| Struct Type    | Argument access   |
+| -------------- | ----------------- |
+| Single tuple   | `{}` or `{0}`     |
+| Multiple tuple | `{0}`, `{1}`, ... |
+| Named fields   | field name        |
+| Unit struct    | inaccessable      |
+
+Field access, method calls and format specs work the same as for [EnumError](derive.EnumError.html#argument-access).
 
 ## Examples
 ```
@@ -95,12 +915,124 @@ struct MyError {
     foo: i8,
 }
 assert_eq!(MyError { foo: 10 }.to_string(), 10.to_string());
+```
+
+## Format function
+`#[format(fn = path)]` calls a function with `&self` and the formatter.
+```
+use {error_proc_macros::StructError, std::fmt::Formatter};
+
+#[derive(StructError)]
+#[format(fn = PathError::write)]
+struct PathError {
+    path: String,
+    line: Option<usize>,
+}
+impl PathError {
+    fn write(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid path {}", self.path)?;
+        match self.line {
+            Some(line) => write!(f, " on line {}", line),
+            None => Ok(()),
+        }
+    }
+}
+assert_eq!(
+    PathError { path: String::from("foo"), line: Some(3) }.to_string(),
+    "invalid path foo on line 3"
+);
+```
+
+# `format_alt`
+Used instead of `format` when formatting with `{:#}`.
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[format = "invalid port"]
+#[format_alt = "invalid port {port}, expected 1 to 65535"]
+struct PortError {
+    port: u32,
+}
+assert_eq!(format!("{}", PortError { port: 0 }), "invalid port");
+assert_eq!(format!("{:#}", PortError { port: 0 }), "invalid port 0, expected 1 to 65535");
+```
+
+# `from`
+`#[from]` on a single tuple struct, or on one of its fields, implements [From] for the value so `?` converts into the struct.
+It can also be on one field of a struct whose other fields are then filled with [Default].
+Like on enum variants, the field is then the source unless it has `no_source`.
+```
+use {
+    error_proc_macros::{Error, StructError},
+    std::num::ParseIntError,
+};
+
+#[derive(Debug, Error, StructError)]
+#[format = "invalid number"]
+#[from]
+struct NumberError(ParseIntError);
+
+#[derive(Debug, Error, StructError)]
+#[format = "invalid port on line {line}"]
+struct PortError {
+    #[from]
+    source: ParseIntError,
+    line: usize,
+}
+
+fn parse(port: &str) -> Result<u16, NumberError> {
+    Ok(port.parse()?)
+}
+assert!(parse("foo").is_err());
+let cause = "foo".parse::<u16>().unwrap_err();
+assert_eq!(PortError::from(cause).to_string(), "invalid port on line 0");
+```
+
+# `transparent`
+Forwards [Display][std::fmt::Display] and [std::error::Error::source] to the field of a single tuple struct, replacing `format`.
+```
+use {
+    error_proc_macros::{Error, StructError},
+    std::num::ParseIntError,
+};
+
+#[derive(Debug, Error, StructError)]
+#[transparent]
+struct ParseError(ParseIntError);
+
+let cause = "foo".parse::<u8>().unwrap_err();
+assert_eq!(ParseError(cause.clone()).to_string(), cause.to_string());
+```
+
+# Formatter flags
+Flags behave the same as with [EnumError](derive.EnumError.html#formatter-flags).
+```
+use error_proc_macros::StructError;
+
+#[derive(StructError)]
+#[format = "{0}"]
+struct MyError(i8);
+
+assert_eq!(format!("{:^5}", MyError(10)), " 10  ");
 ```
  */
-#[proc_macro_derive(StructError, attributes(display, format))]
+#[proc_macro_derive(
+    StructError,
+    attributes(
+        backtrace,
+        code,
+        display,
+        error_defaults,
+        format,
+        format_alt,
+        from,
+        transparent
+    )
+)]
 #[proc_macro_error]
 pub fn struct_error(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    StructError::from(&input).into_token_stream().into()
+    derive_output(StructError::from(&input).into_token_stream())
 }
@@ -1,10 +1,16 @@
 pub use {
     proc_macro::TokenStream,
-    proc_macro_error::{proc_macro_error, Diagnostic, Level},
-    quote::{quote, ToTokens},
+    proc_macro2::Span,
+    proc_macro_error::{proc_macro_error, set_dummy, Diagnostic, Level},
+    quote::{format_ident, quote, quote_spanned, ToTokens},
     syn::{
-        parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, Generics, Ident, Lit,
-        LitStr, Meta, Type, Variant,
+        ext::IdentExt,
+        parse::{Parse, ParseStream},
+        parse_macro_input,
+        punctuated::Punctuated,
+        spanned::Spanned,
+        Attribute, Data, DeriveInput, Expr, ExprLit, Field, Fields, Generics, Ident, Index, Lit,
+        LitInt, LitStr, Member, Meta, Path, Token, Type, Variant, Visibility,
     },
 };
 
@@ -1,11 +1,35 @@
 use crate::{
-    common::{attrs_get_lit_str, display_field},
+    common::{
+        alternate_ident, attrs_contains, attrs_get_code, attrs_get_defaults, attrs_get_display,
+        attrs_get_format, attrs_get_format_fn, backtrace_items, check_backtrace_fields, code_items,
+        display_impl, from_impl, humanized_format, inherent_impl, pad_flags, resolve_field,
+        source_expr, validate_attrs, write_format, ErrorField, FieldDisplay,
+    },
+    format_string::FormatString,
     prelude::*,
 };
 
+/// The attributes [StructError] reads
+const ATTRIBUTES: &[&str] = &[
+    "backtrace",
+    "code",
+    "display",
+    "error_defaults",
+    "format",
+    "format_alt",
+    "from",
+    "transparent",
+];
+
 pub struct StructError<'a> {
+    code: Option<LitStr>,
+    /// The `display` attribute on the struct, which is called with `&self`
+    display: Option<FieldDisplay>,
     ident: &'a Ident,
-    format: &'a LitStr,
+    format: Option<FormatString>,
+    format_alt: Option<FormatString>,
+    /// `#[format(fn = ...)]`, which is called with `&self` and the formatter
+    format_fn: Option<Path>,
     generics: &'a Generics,
     variant: StructErrorVariant<'a>,
 }
@@ -19,168 +43,352 @@ impl<'a> From<&'a DeriveInput> for StructError<'a> {
             .help(String::from("remove"))
             .abort()
         };
+        // only newtypes can have `from` on the struct
+        let allowed: &[&str] = if matches!(&data.fields, Fields::Unnamed(fields) if fields.unnamed.len() == 1)
+        {
+            &[
+                "code",
+                "display",
+                "error_defaults",
+                "format",
+                "format_alt",
+                "from",
+                "transparent",
+            ]
+        } else {
+            &[
+                "code",
+                "display",
+                "error_defaults",
+                "format",
+                "format_alt",
+                "transparent",
+            ]
+        };
+
+        validate_attrs(&input.attrs, ATTRIBUTES, allowed, "this struct");
+        for field in &data.fields {
+            validate_attrs(
+                &field.attrs,
+                ATTRIBUTES,
+                &["backtrace", "display", "from"],
+                "this field",
+            );
+        }
+        let display = attrs_get_display(&input.attrs);
+        let format_fn = attrs_get_format_fn(&input.attrs);
+        let mut format = attrs_get_format(&input.attrs, "format");
+        let format_alt = attrs_get_format(&input.attrs, "format_alt");
+        let variant = StructErrorVariant::new(&input.attrs, &data.fields);
+
+        check_backtrace_fields(variant.fields());
+
+        if format_fn.is_some() && (format_alt.is_some() || variant.is_transparent()) {
+            Diagnostic::spanned(
+                input.ident.span(),
+                Level::Error,
+                String::from(
+                    "`#[format(fn = ...)]` cannot be combined with `format_alt` or `transparent`",
+                ),
+            )
+            .help(String::from(
+                "check `f.alternate()` in the function instead of using `format_alt`",
+            ))
+            .emit()
+        }
+        if display.is_some()
+            && (format.is_some()
+                || format_fn.is_some()
+                || format_alt.is_some()
+                || variant.is_transparent())
+        {
+            Diagnostic::spanned(
+                input.ident.span(),
+                Level::Error,
+                String::from(
+                    "`display` on a struct cannot be combined with `format`, `format_alt` or `transparent`",
+                ),
+            )
+            .help(String::from("remove either `#[display(...)]` or the other attributes"))
+            .emit()
+        }
+        if attrs_get_defaults(&input.attrs).humanize
+            && format.is_none()
+            && format_fn.is_none()
+            && display.is_none()
+            && !variant.is_transparent()
+        {
+            format = Some(humanized_format(&input.ident, variant.fields()));
+        }
+
+        if variant.is_transparent() && (format.is_some() || format_alt.is_some()) {
+            Diagnostic::spanned(
+                input.ident.span(),
+                Level::Error,
+                String::from("`transparent` structs cannot have a `format` or `format_alt`"),
+            )
+            .help(String::from(
+                "remove either `#[transparent]` or `#[format = \"...\"]`",
+            ))
+            .emit()
+        }
 
         Self {
+            code: attrs_get_code(&input.attrs, None),
+            display,
             ident: &input.ident,
-            format: attrs_get_lit_str(&input.attrs, "format").unwrap_or_else(|_| {
-                Diagnostic::new(
-                    Level::Error,
-                    String::from(
-                        "failed to get required attribute `format` for macro `StructError`",
-                    ),
-                )
-                .help(String::from("add `#[format = \"...\"]`"))
-                .abort()
-            }),
+            format,
+            format_alt,
+            format_fn,
             generics: &input.generics,
-            variant: StructErrorVariant::from(&data.fields),
+            variant,
         }
     }
 }
+impl StructError<'_> {
+    /// Creates a display implementation, which uses the `display` or format function of the struct if it has one
+    fn to_display_impl(&self) -> TokenStream2 {
+        let pad = pad_flags();
+        let value = match (&self.display, &self.format_fn) {
+            (Some(FieldDisplay::Call(display)), _) => quote! { (#display)(self) },
+            (Some(FieldDisplay::With(with)), _) => quote! { #with(self) },
+            (Some(FieldDisplay::Join { .. } | FieldDisplay::Optional(_)), _) => {
+                Diagnostic::spanned(
+                    self.ident.span(),
+                    Level::Error,
+                    String::from("`join` and `optional` only work on fields"),
+                )
+                .help(String::from("move the `display` attribute to a field"))
+                .emit();
+                quote! { "" }
+            }
+            (None, Some(format_fn)) => {
+                return display_impl(
+                    self.ident,
+                    self.generics,
+                    quote! {
+                        #pad
+                        #format_fn(self, f)
+                    },
+                )
+            }
+            (None, None) => {
+                return self.variant.to_display_impl(
+                    self.ident,
+                    self.generics,
+                    self.format.as_ref(),
+                    self.format_alt.as_ref(),
+                )
+            }
+        };
+
+        display_impl(
+            self.ident,
+            self.generics,
+            quote! {
+                #pad
+                write!(f, "{}", #value)
+            },
+        )
+    }
+}
 impl ToTokens for StructError<'_> {
     fn to_tokens(&self, output: &mut TokenStream2) {
-        output.extend(
-            self.variant
-                .to_display_impl(self.ident, self.generics, self.format),
-        );
+        output.extend(self.to_display_impl());
+        output.extend(self.variant.to_from_impl(self.ident, self.generics));
+        output.extend(inherent_impl(
+            self.ident,
+            self.generics,
+            self.code
+                .iter()
+                .map(|code| code_items(quote! { #code }, &[code]))
+                .chain(self.variant.to_backtrace_body().map(backtrace_items))
+                .collect(),
+        ));
     }
 }
 
 pub enum StructErrorVariant<'a> {
-    Named(Vec<(Option<&'a LitStr>, &'a Ident)>),
-    SingleUnnamed,
+    Named(Vec<ErrorField<'a>>),
+    SingleUnnamed {
+        field: ErrorField<'a>,
+        /// Forward everything to the field
+        transparent: bool,
+    },
     Unit,
-    Unnamed(Vec<Option<&'a LitStr>>),
+    Unnamed(Vec<ErrorField<'a>>),
+}
+impl<'a> StructErrorVariant<'a> {
+    pub fn new(attrs: &'a [Attribute], fields: &'a Fields) -> Self {
+        let transparent = attrs_contains(attrs, "transparent");
+
+        if transparent && !matches!(fields, Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1)
+        {
+            Diagnostic::new(
+                Level::Error,
+                String::from("`transparent` only works on structs with a single unnamed field"),
+            )
+            .help(String::from("remove `#[transparent]`"))
+            .emit()
+        }
+
+        let mut variant = match fields {
+            Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                let mut field = ErrorField::from_fields(fields).remove(0);
+
+                // a newtype can also have `from` on the struct
+                if attrs_contains(attrs, "from") {
+                    field.from = true;
+                    field.source |= !attrs_contains(&unnamed.unnamed[0].attrs, "no_source");
+                }
+
+                Self::SingleUnnamed { field, transparent }
+            }
+            Fields::Named(_) => Self::Named(ErrorField::from_fields(fields)),
+            Fields::Unnamed(_) => Self::Unnamed(ErrorField::from_fields(fields)),
+            Fields::Unit => Self::Unit,
+        };
+
+        for field in variant
+            .fields_mut()
+            .iter_mut()
+            .filter(|field| field.from)
+            .skip(1)
+        {
+            Diagnostic::spanned(
+                field.ty.span(),
+                Level::Error,
+                String::from("a struct can only be converted from one field"),
+            )
+            .help(String::from("remove the other `#[from]` attributes"))
+            .emit();
+            field.from = false;
+        }
+
+        variant
+    }
+
+    fn fields_mut(&mut self) -> &mut [ErrorField<'a>] {
+        match self {
+            Self::Named(fields) | Self::Unnamed(fields) => fields,
+            Self::SingleUnnamed { field, .. } => std::slice::from_mut(field),
+            Self::Unit => &mut [],
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        matches!(
+            self,
+            Self::SingleUnnamed {
+                transparent: true,
+                ..
+            }
+        )
+    }
 }
 impl StructErrorVariant<'_> {
+    /// Creates the body of [std::error::Error::source] if a field is marked as `source`
+    pub fn to_source_body(&self) -> Option<TokenStream2> {
+        let field = match self {
+            Self::Named(fields) | Self::Unnamed(fields) => {
+                fields.iter().find(|field| field.source)?
+            }
+            Self::SingleUnnamed {
+                transparent: true, ..
+            } => {
+                return Some(quote! {
+                    std::error::Error::source(&self.0)
+                })
+            }
+            Self::SingleUnnamed { field, .. } => Some(field).filter(|field| field.source)?,
+            Self::Unit => return None,
+        };
+        let member = &field.member;
+
+        Some(source_expr(field, &quote! { &self.#member }))
+    }
+
+    /// Creates the body of `backtrace` if a field is a backtrace
+    pub fn to_backtrace_body(&self) -> Option<TokenStream2> {
+        let field = self.fields().iter().find(|field| field.backtrace)?;
+        let member = &field.member;
+
+        Some(quote! { Some(&self.#member) })
+    }
+
+    /// Implements `From` for the type of the field marked as `from`
+    pub fn to_from_impl(&self, onto: &Ident, generics: &Generics) -> Option<TokenStream2> {
+        from_impl(onto, generics, &quote! { Self }, self.fields())
+    }
+
+    /// The fields that can be accessed in `format`
+    pub fn fields(&self) -> &[ErrorField<'_>] {
+        match self {
+            Self::Named(fields) | Self::Unnamed(fields) => fields,
+            Self::SingleUnnamed { field, .. } => std::slice::from_ref(field),
+            Self::Unit => &[],
+        }
+    }
+
     /// Creates a display implementation
     pub fn to_display_impl(
         &self,
         self_ident: &Ident,
         self_generics: &Generics,
-        self_format: &LitStr,
+        self_format: Option<&FormatString>,
+        self_format_alt: Option<&FormatString>,
     ) -> TokenStream2 {
-        let (impl_generics, ty_generics, where_clause) = self_generics.split_for_impl();
+        if self.is_transparent() {
+            // the formatter is forwarded as is, so the field handles the flags itself
+            return display_impl(
+                self_ident,
+                self_generics,
+                quote! {
+                    std::fmt::Display::fmt(&self.0, f)
+                },
+            );
+        }
+        let self_format = self_format.cloned().unwrap_or_else(|| {
+            Diagnostic::spanned(
+                self_ident.span(),
+                Level::Error,
+                String::from("failed to get required attribute `format` for macro `StructError`"),
+            )
+            .help(String::from("add `#[format = \"...\"]`"))
+            .emit();
 
-        match self {
-            Self::Named(fields) => {
-                let declarations = fields
-                    .iter()
-                    .map(|(display, field)| {
-                        (
-                            display,
-                            field,
-                            quote! {
-                                self.#field
-                            },
-                        )
-                    })
-                    .map(|(display, field, self_field)| {
-                        let display = display_field(display, &self_field);
-
-                        quote! {
-                            let #field = #display;
-                        }
-                    })
-                    .collect::<TokenStream2>();
+            FormatString::literal(&self_ident.to_string(), self_ident.span())
+        });
 
-                quote! {
-                    #[automatically_derived]
-                    impl #impl_generics std::fmt::Display for #self_ident #ty_generics #where_clause {
-                        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                            #declarations
-
-                            write!(f, #self_format)
-                        }
-                    }
-                }
-            }
-            Self::SingleUnnamed => {
-                quote! {
-                    #[automatically_derived]
-                    impl #impl_generics std::fmt::Display for #self_ident #ty_generics #where_clause {
-                        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                            write!(f, #self_format, self.0)
-                        }
-                    }
-                }
-            }
-            Self::Unit => {
-                quote! {
-                    #[automatically_derived]
-                    impl #impl_generics std::fmt::Display for #self_ident #ty_generics #where_clause {
-                        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                            write!(f, #self_format)
-                        }
-                    }
-                }
-            }
-            Self::Unnamed(displays) => {
-                let definitions = displays
-                    .iter()
-                    .enumerate()
-                    .map(|(i, display)| {
-                        (
-                            display,
-                            format!("arg_{i}").parse().unwrap_or_else(|error| {
-                                syn::Error::from(error).into_compile_error()
-                            }),
-                            format!("self.{i}").parse().unwrap_or_else(|error| {
-                                syn::Error::from(error).into_compile_error()
-                            }),
-                        )
-                    })
-                    .map(|(display, arg_var, self_var)| {
-                        let display = display_field(display, &self_var);
-                        quote! {
-                            let #arg_var = #display;
-                        }
-                    })
-                    .collect::<TokenStream2>();
+        let known = self
+            .fields()
+            .iter()
+            .map(ErrorField::name)
+            .collect::<Vec<_>>();
+        let write = write_format(&self_format, self_format_alt, &known, |argument| {
+            resolve_field(self.fields(), argument, |field| {
+                let member = &field.member;
 
-                quote! {
-                    #[automatically_derived]
-                    impl #impl_generics std::fmt::Display for #self_ident #ty_generics #where_clause {
-                        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-                            #definitions
-
-                            write!(f, #self_format)
-                        }
-                    }
-                }
-            }
-        }
-    }
-}
-impl<'a> From<&'a Fields> for StructErrorVariant<'a> {
-    fn from(fields: &'a Fields) -> Self {
-        match fields {
-            Fields::Named(fields) => Self::Named(
-                fields
-                    .named
-                    .iter()
-                    .map(|field| {
-                        (
-                            attrs_get_lit_str(&field.attrs, "display").ok(),
-                            field.ident.as_ref().unwrap(),
-                        )
-                    })
-                    .collect(),
-            ),
-            Fields::Unnamed(fields) => {
-                if fields.unnamed.len() == 1 {
-                    Self::SingleUnnamed
-                } else {
-                    Self::Unnamed(
-                        fields
-                            .unnamed
-                            .iter()
-                            .map(|field| attrs_get_lit_str(&field.attrs, "display").ok())
-                            .collect(),
-                    )
-                }
+                quote! { self.#member }
+            })
+        });
+        let pad = pad_flags();
+        let alternate = self_format_alt.map(|_| {
+            let alternate = alternate_ident();
+
+            quote! {
+                let #alternate = f.alternate();
             }
-            Fields::Unit => Self::Unit,
-        }
+        });
+
+        display_impl(
+            self_ident,
+            self_generics,
+            quote! {
+                #pad
+                #alternate
+                #write
+            },
+        )
     }
 }